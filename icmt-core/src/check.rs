@@ -0,0 +1,206 @@
+//! Validation of data sent to and from the service.
+//!
+//! The Candid types admit values that the terminal cannot use as given
+//! (e.g., color components above 255, or nodes whose elements stray outside of them);
+//! these checks find them, and say where.
+
+use crate::glyph::text_dim;
+use crate::types::{
+    event::{Event, EventInfo},
+    graphics::{
+        Bitmap, Color, Elm, Fill, Node, Out, Pos, Rect, Result as GraphicsResult, TextElm,
+    },
+    nat_ceil, nat_u32, nat_u8, Nat,
+};
+
+/// Result of a check: a message describing the first problem found, if any.
+pub type CheckResult = Result<(), String>;
+
+/// Check that each color component is a byte.
+pub fn check_color(c: &Color) -> CheckResult {
+    let (r, g, b) = c;
+    for n in [r, g, b].iter() {
+        if nat_u8(n).is_none() {
+            return Err(format!("color component {} exceeds 255", n.0));
+        }
+    }
+    Ok(())
+}
+
+/// Check that each of the rectangle's coordinates (and its far corner) fits in a `u32`.
+pub fn check_rect(r: &Rect) -> CheckResult {
+    let right = candid::Nat(&r.pos.x.0 + &r.dim.width.0);
+    let bottom = candid::Nat(&r.pos.y.0 + &r.dim.height.0);
+    if nat_u32(&right).is_none() || nat_u32(&bottom).is_none() {
+        Err(format!("rect {:?} exceeds the native integer range", r))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check a fill's color, and that an open fill's border width fits in a `u32`.
+pub fn check_fill(f: &Fill) -> CheckResult {
+    match f {
+        Fill::None => Ok(()),
+        Fill::Closed(c) => check_color(c),
+        Fill::Open(c, w) => {
+            check_color(c)?;
+            match nat_u32(w) {
+                Some(_) => Ok(()),
+                None => Err(format!(
+                    "border width {} exceeds the native integer range",
+                    w.0
+                )),
+            }
+        }
+    }
+}
+
+/// Does the first rectangle contain the second?
+pub fn rect_contains(r1: &Rect, r2: &Rect) -> bool {
+    r1.pos.x.0 <= r2.pos.x.0
+        && r1.pos.y.0 <= r2.pos.y.0
+        && &r1.pos.x.0 + &r1.dim.width.0 >= &r2.pos.x.0 + &r2.dim.width.0
+        && &r1.pos.y.0 + &r1.dim.height.0 >= &r2.pos.y.0 + &r2.dim.height.0
+}
+
+/// Bounding rectangle of an element, relative to its parent's position.
+pub fn bounding_rect(elm: &Elm) -> Rect {
+    match elm {
+        Elm::Rect(r, _) => r.clone(),
+        Elm::Node(node) => node.rect.clone(),
+        Elm::Bitmap(b) => b.rect.clone(),
+        Elm::Text(t) => {
+            let (width, height) = text_dim(&t.text, nat_ceil(&t.zoom));
+            Rect::new(
+                t.pos.x.clone(),
+                t.pos.y.clone(),
+                Nat::from(width),
+                Nat::from(height),
+            )
+        }
+    }
+}
+
+/// Check a node's rect and fill, and each of its elements; each must lie within the node.
+pub fn check_node(node: &Node) -> CheckResult {
+    check_rect(&node.rect)?;
+    check_fill(&node.fill)?;
+    let bound = Rect::new(
+        Nat::from(0),
+        Nat::from(0),
+        node.rect.dim.width.clone(),
+        node.rect.dim.height.clone(),
+    );
+    for elm in node.elms.iter() {
+        check_elm(elm)?;
+        let r = bounding_rect(elm);
+        if !rect_contains(&bound, &r) {
+            return Err(format!(
+                "node rect {:?} does not contain element rect {:?}",
+                node.rect, r
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check an element, and all of its sub-elements.
+pub fn check_elm(elm: &Elm) -> CheckResult {
+    match elm {
+        Elm::Rect(r, f) => {
+            check_rect(r)?;
+            check_fill(f)
+        }
+        Elm::Node(node) => check_node(node),
+        Elm::Text(t) => check_text(t),
+        Elm::Bitmap(b) => check_bitmap(b),
+    }
+}
+
+/// Check a bitmap's rect, that its pixel count matches its size, and its palette indices.
+pub fn check_bitmap(b: &Bitmap) -> CheckResult {
+    check_rect(&b.rect)?;
+    let (width, height) = match (nat_u32(&b.width), nat_u32(&b.height)) {
+        (Some(w), Some(h)) => (w as usize, h as usize),
+        _ => return Err(format!("bitmap size {}x{} is too large", b.width.0, b.height.0)),
+    };
+    let expected = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(b.pixel_size()));
+    if expected != Some(b.pixels.len()) {
+        return Err(format!(
+            "bitmap of size {}x{} has {} bytes of pixels, not {:?}",
+            width,
+            height,
+            b.pixels.len(),
+            expected
+        ));
+    }
+    if let Some(palette) = &b.palette {
+        for c in palette.iter() {
+            check_color(c)?
+        }
+        if let Some(i) = b.pixels.iter().find(|i| **i as usize >= palette.len()) {
+            return Err(format!(
+                "bitmap pixel {} indexes past its palette of {} colors",
+                i,
+                palette.len()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check a text element's color and zoom, and that its laid-out glyphs fit in a `u32`.
+pub fn check_text(t: &TextElm) -> CheckResult {
+    check_color(&t.color)?;
+    if nat_u32(&t.zoom).is_none() {
+        return Err(format!("text zoom {} exceeds the native integer range", t.zoom.0));
+    }
+    check_rect(&bounding_rect(&Elm::Text(t.clone())))
+}
+
+/// Check the graphics output of a service call.
+pub fn check_graphics(res: &GraphicsResult) -> CheckResult {
+    match res {
+        GraphicsResult::Ok(Out::Draw(elm)) => check_elm(elm),
+        GraphicsResult::Ok(Out::Redraw(elms)) => {
+            for (name, elm) in elms.iter() {
+                check_elm(elm).map_err(|e| format!("in {:?}: {}", name, e))?
+            }
+            Ok(())
+        }
+        GraphicsResult::Ok(Out::Commands(_)) | GraphicsResult::Err(_) => Ok(()),
+    }
+}
+
+fn check_mouse_pos(pos: &Pos) -> CheckResult {
+    match pos.to_u32() {
+        Some(_) => Ok(()),
+        None => Err(format!("mouse position {:?} exceeds the native integer range", pos)),
+    }
+}
+
+/// Check an event record, including its user's colors.
+pub fn check_event_info(ev: &EventInfo) -> CheckResult {
+    let (fg, bg) = &ev.user_info.text_color;
+    check_color(fg)?;
+    check_color(bg)?;
+    match &ev.event {
+        Event::KeyDown(keys) | Event::KeyUp(keys) => {
+            if keys.iter().any(|k| k.key.is_empty()) {
+                Err("empty key name".to_string())
+            } else {
+                Ok(())
+            }
+        }
+        Event::MouseDown(info) | Event::MouseUp(info) => check_mouse_pos(&info.pos),
+        Event::MouseMove(pos) => check_mouse_pos(pos),
+        Event::WindowSize(dim) => match dim.to_u32() {
+            Some(_) => Ok(()),
+            None => Err(format!("window size {:?} exceeds the native integer range", dim)),
+        },
+        _ => Ok(()),
+    }
+}
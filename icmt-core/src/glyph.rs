@@ -0,0 +1,576 @@
+//! Glyphs of the 5x5 monospace bitmap font.
+//!
+//! A port of the Motoko glyph table, `src/glyph/Mono5x5.mo`, for drawing text on the client.
+
+/// Width and height of every glyph, in cells.
+pub const GLYPH_SIZE: u32 = 5;
+
+/// Space between glyphs, and between lines of glyphs, in cells.
+pub const GLYPH_PAD: u32 = 1;
+
+/// Rows of a glyph, top to bottom; in each row, the high bit (of five) is the leftmost cell.
+pub type GlyphBits = [u8; 5];
+
+/// Bits of the glyph for a character, if the font has one.
+///
+/// Upper-case letters share the glyphs of lower-case ones.
+#[rustfmt::skip]
+pub fn bits_of_char(c: char) -> Option<GlyphBits> {
+    let bits = match c.to_ascii_lowercase() {
+        '☺' => [
+            0b01110,
+            0b10101,
+            0b01110,
+            0b10001,
+            0b01110,
+        ],
+        'ļ' => [
+            0b11111,
+            0b11011,
+            0b10101,
+            0b11011,
+            0b11111,
+        ],
+        'ķ' => [
+            0b00100,
+            0b01010,
+            0b00100,
+            0b00110,
+            0b00110,
+        ],
+        '◊' => [
+            0b00100,
+            0b01010,
+            0b10001,
+            0b01010,
+            0b00100,
+        ],
+        '⇲' => [
+            0b10100,
+            0b01101,
+            0b11101,
+            0b00001,
+            0b01111,
+        ],
+        '█' => [
+            0b11111,
+            0b11111,
+            0b11111,
+            0b11111,
+            0b11111,
+        ],
+        '░' => [
+            0b10101,
+            0b01010,
+            0b10101,
+            0b01010,
+            0b10101,
+        ],
+        '━' => [
+            0b00000,
+            0b00000,
+            0b10101,
+            0b00000,
+            0b00000,
+        ],
+        '►' => [
+            0b00100,
+            0b00110,
+            0b10111,
+            0b00110,
+            0b00100,
+        ],
+        '→' => [
+            0b00100,
+            0b00010,
+            0b11111,
+            0b00010,
+            0b00100,
+        ],
+        '?' => [
+            0b01110,
+            0b10001,
+            0b00110,
+            0b00000,
+            0b00100,
+        ],
+        '*' => [
+            0b00000,
+            0b00100,
+            0b01010,
+            0b00100,
+            0b00000,
+        ],
+        '0' => [
+            0b01110,
+            0b10001,
+            0b10101,
+            0b10001,
+            0b01110,
+        ],
+        '1' => [
+            0b01100,
+            0b00100,
+            0b00100,
+            0b00100,
+            0b01110,
+        ],
+        '2' => [
+            0b01110,
+            0b10001,
+            0b00110,
+            0b01000,
+            0b11111,
+        ],
+        '3' => [
+            0b01110,
+            0b10001,
+            0b00110,
+            0b10001,
+            0b01110,
+        ],
+        '4' => [
+            0b00101,
+            0b00101,
+            0b01001,
+            0b11111,
+            0b00001,
+        ],
+        '5' => [
+            0b11111,
+            0b10000,
+            0b01110,
+            0b00001,
+            0b11110,
+        ],
+        '6' => [
+            0b01110,
+            0b10000,
+            0b11110,
+            0b10001,
+            0b01110,
+        ],
+        '7' => [
+            0b11111,
+            0b00001,
+            0b00010,
+            0b00100,
+            0b01000,
+        ],
+        '8' => [
+            0b01110,
+            0b10001,
+            0b01110,
+            0b10001,
+            0b01110,
+        ],
+        '9' => [
+            0b01110,
+            0b10001,
+            0b01111,
+            0b00001,
+            0b01110,
+        ],
+        'a' => [
+            0b00100,
+            0b01010,
+            0b10001,
+            0b11111,
+            0b10001,
+        ],
+        'b' => [
+            0b11110,
+            0b10001,
+            0b11110,
+            0b10001,
+            0b11110,
+        ],
+        'c' => [
+            0b01111,
+            0b10000,
+            0b10000,
+            0b10000,
+            0b01111,
+        ],
+        'd' => [
+            0b11110,
+            0b10001,
+            0b10001,
+            0b10001,
+            0b11110,
+        ],
+        'e' => [
+            0b11111,
+            0b10000,
+            0b11100,
+            0b10000,
+            0b11111,
+        ],
+        'f' => [
+            0b11111,
+            0b10000,
+            0b11100,
+            0b10000,
+            0b10000,
+        ],
+        'g' => [
+            0b01110,
+            0b10000,
+            0b10111,
+            0b10001,
+            0b01110,
+        ],
+        'h' => [
+            0b10001,
+            0b10001,
+            0b10001,
+            0b11111,
+            0b10001,
+        ],
+        'i' => [
+            0b01110,
+            0b00100,
+            0b00100,
+            0b00100,
+            0b01110,
+        ],
+        'j' => [
+            0b00011,
+            0b00001,
+            0b00001,
+            0b10001,
+            0b01110,
+        ],
+        'k' => [
+            0b10001,
+            0b10010,
+            0b11100,
+            0b10010,
+            0b10001,
+        ],
+        'l' => [
+            0b10000,
+            0b10000,
+            0b10000,
+            0b10000,
+            0b11111,
+        ],
+        'm' => [
+            0b10001,
+            0b11011,
+            0b10101,
+            0b10001,
+            0b10001,
+        ],
+        'n' => [
+            0b10001,
+            0b11001,
+            0b10101,
+            0b10011,
+            0b10001,
+        ],
+        'o' => [
+            0b01110,
+            0b10001,
+            0b10001,
+            0b10001,
+            0b01110,
+        ],
+        'p' => [
+            0b11110,
+            0b10001,
+            0b11110,
+            0b10000,
+            0b10000,
+        ],
+        'q' => [
+            0b01110,
+            0b10001,
+            0b10101,
+            0b01110,
+            0b00001,
+        ],
+        'r' => [
+            0b11110,
+            0b10001,
+            0b11110,
+            0b10010,
+            0b10001,
+        ],
+        's' => [
+            0b01111,
+            0b10000,
+            0b01110,
+            0b00001,
+            0b11110,
+        ],
+        't' => [
+            0b11111,
+            0b00100,
+            0b00100,
+            0b00100,
+            0b00100,
+        ],
+        'u' => [
+            0b10001,
+            0b10001,
+            0b10001,
+            0b10001,
+            0b01110,
+        ],
+        'v' => [
+            0b10001,
+            0b10001,
+            0b01010,
+            0b01010,
+            0b00100,
+        ],
+        'w' => [
+            0b10001,
+            0b10001,
+            0b10101,
+            0b11011,
+            0b10001,
+        ],
+        'x' => [
+            0b10001,
+            0b01010,
+            0b00100,
+            0b01010,
+            0b10001,
+        ],
+        'y' => [
+            0b10001,
+            0b01010,
+            0b00100,
+            0b00100,
+            0b00100,
+        ],
+        'z' => [
+            0b11111,
+            0b00010,
+            0b00100,
+            0b01000,
+            0b11111,
+        ],
+        ' ' => [
+            0b00000,
+            0b00000,
+            0b00000,
+            0b00000,
+            0b00000,
+        ],
+        '.' => [
+            0b00000,
+            0b00000,
+            0b01110,
+            0b01110,
+            0b01110,
+        ],
+        ',' => [
+            0b00000,
+            0b00000,
+            0b00010,
+            0b00010,
+            0b00100,
+        ],
+        ';' => [
+            0b01110,
+            0b01110,
+            0b00000,
+            0b01110,
+            0b11000,
+        ],
+        ':' => [
+            0b01110,
+            0b01110,
+            0b00000,
+            0b01110,
+            0b01110,
+        ],
+        '<' => [
+            0b00010,
+            0b00100,
+            0b01000,
+            0b00100,
+            0b00010,
+        ],
+        '>' => [
+            0b01000,
+            0b00100,
+            0b00010,
+            0b00100,
+            0b01000,
+        ],
+        '(' => [
+            0b00010,
+            0b00100,
+            0b00100,
+            0b00100,
+            0b00010,
+        ],
+        ')' => [
+            0b00100,
+            0b00010,
+            0b00010,
+            0b00010,
+            0b00100,
+        ],
+        '{' => [
+            0b00011,
+            0b00100,
+            0b01010,
+            0b00100,
+            0b00011,
+        ],
+        '}' => [
+            0b11000,
+            0b00100,
+            0b01010,
+            0b00100,
+            0b11000,
+        ],
+        ']' => [
+            0b00110,
+            0b00010,
+            0b00010,
+            0b00010,
+            0b00110,
+        ],
+        '[' => [
+            0b01100,
+            0b01000,
+            0b01000,
+            0b01000,
+            0b01100,
+        ],
+        '"' => [
+            0b01010,
+            0b01010,
+            0b01010,
+            0b00000,
+            0b00000,
+        ],
+        '~' => [
+            0b00000,
+            0b01001,
+            0b10110,
+            0b00000,
+            0b00000,
+        ],
+        '@' => [
+            0b11111,
+            0b10001,
+            0b10111,
+            0b10100,
+            0b11111,
+        ],
+        '\'' => [
+            0b00100,
+            0b00100,
+            0b00100,
+            0b00000,
+            0b00000,
+        ],
+        '\\' => [
+            0b10000,
+            0b01000,
+            0b00100,
+            0b00010,
+            0b00001,
+        ],
+        '=' => [
+            0b00000,
+            0b11111,
+            0b00000,
+            0b11111,
+            0b00000,
+        ],
+        '_' => [
+            0b00000,
+            0b00000,
+            0b00000,
+            0b00000,
+            0b11111,
+        ],
+        '-' => [
+            0b00000,
+            0b00000,
+            0b11111,
+            0b00000,
+            0b00000,
+        ],
+        '+' => [
+            0b00100,
+            0b00100,
+            0b11111,
+            0b00100,
+            0b00100,
+        ],
+        '/' => [
+            0b00001,
+            0b00010,
+            0b00100,
+            0b01000,
+            0b10000,
+        ],
+        '`' => [
+            0b01000,
+            0b00100,
+            0b00010,
+            0b00000,
+            0b00000,
+        ],
+        '#' => [
+            0b01010,
+            0b11111,
+            0b01010,
+            0b11111,
+            0b01010,
+        ],
+        '!' => [
+            0b00011,
+            0b00011,
+            0b00110,
+            0b00000,
+            0b01100,
+        ],
+        _ => return None,
+    };
+    Some(bits)
+}
+
+/// Is the cell at column `i` and row `j` of the glyph lit?
+pub fn glyph_cell(bits: &GlyphBits, i: u32, j: u32) -> bool {
+    bits[j as usize] & (1 << (GLYPH_SIZE - 1 - i)) != 0
+}
+
+/// Lay out text as glyph positions (column and line, in glyphs), with the glyph's bits.
+///
+/// Text flows to the right, and down at each newline; characters without glyphs leave a blank.
+pub fn layout(text: &str) -> Vec<(u32, u32, Option<GlyphBits>)> {
+    let mut glyphs = vec![];
+    for (line, chars) in text.split('\n').enumerate() {
+        for (col, c) in chars.chars().enumerate() {
+            glyphs.push((col as u32, line as u32, bits_of_char(c)));
+        }
+    }
+    glyphs
+}
+
+/// Width and height of the laid-out text, in pixels, with each cell `zoom` pixels square.
+pub fn text_dim(text: &str, zoom: u32) -> (u32, u32) {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let cols = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u32;
+    let rows = lines.len() as u32;
+    let extent = |n: u32| {
+        if n == 0 {
+            0
+        } else {
+            n.saturating_mul(GLYPH_SIZE + GLYPH_PAD)
+                .saturating_sub(GLYPH_PAD)
+                .saturating_mul(zoom)
+        }
+    };
+    (extent(cols), extent(rows))
+}
@@ -6,4 +6,6 @@ extern crate candid;
 extern crate serde;
 extern crate serde_bytes;
 
+pub mod check;
+pub mod glyph;
 pub mod types;
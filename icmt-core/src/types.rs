@@ -3,20 +3,69 @@
 use num_traits::cast::ToPrimitive;
 pub type Nat = candid::Nat;
 
+/// Natural number as a `u32`, if it fits.
+pub fn nat_u32(n: &Nat) -> Option<u32> {
+    n.0.to_u32()
+}
+
+/// Natural number as a `u8`, if it fits.
+pub fn nat_u8(n: &Nat) -> Option<u8> {
+    n.0.to_u8()
+}
+
+/// Natural number as a `u32`, saturating at `u32::MAX`.
 pub fn nat_ceil(n: &Nat) -> u32 {
-    n.0.to_u32().unwrap()
+    nat_u32(n).unwrap_or(u32::MAX)
+}
+
+/// Natural number as an `i32` (e.g., an SDL coordinate), saturating at `i32::MAX`.
+pub fn nat_i32(n: &Nat) -> i32 {
+    n.0.to_i32().unwrap_or(i32::MAX)
 }
 
+/// Natural number as a `u8`, saturating at 255.
 pub fn byte_ceil(n: &Nat) -> u8 {
-    match n.0.to_u8() {
-        Some(byte) => byte,
-        None => 255,
+    nat_u8(n).unwrap_or(255)
+}
+
+/// Message language
+pub mod lang {
+    use super::Nat;
+    use candid::{CandidType, Deserialize};
+
+    /// Directions in two dimensional space.
+    #[derive(Debug, Clone, CandidType, Deserialize, Eq, PartialEq, Hash)]
+    pub enum Dir2D {
+        #[serde(rename = "up")]
+        Up,
+        #[serde(rename = "down")]
+        Down,
+        #[serde(rename = "left")]
+        Left,
+        #[serde(rename = "right")]
+        Right,
+    }
+
+    /// Symbolic name (n-ary tree).
+    #[derive(Debug, Clone, CandidType, Deserialize, Eq, PartialEq, Hash)]
+    pub enum Name {
+        Void,
+        Atom(Atom),
+        TaggedTuple(Box<Name>, Vec<Name>),
+    }
+
+    /// Atomic name
+    #[derive(Debug, Clone, CandidType, Deserialize, Eq, PartialEq, Hash)]
+    pub enum Atom {
+        Bool(bool),
+        Nat(Nat),
+        String(String),
     }
 }
 
 /// Terminal events, locally buffered as input to service.
 pub mod event {
-    use candid::{CandidType, Deserialize, Nat};
+    use candid::{CandidType, Deserialize, Int, Nat};
 
     /// User information for identifying events' user origins.
     #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
@@ -48,12 +97,27 @@ pub mod event {
         Quit,
         #[serde(rename = "keyDown")]
         KeyDown(Vec<KeyEventInfo>),
+        #[serde(rename = "keyUp")]
+        KeyUp(Vec<KeyEventInfo>),
         #[serde(rename = "mouseDown")]
-        MouseDown(super::graphics::Pos),
+        MouseDown(MouseButtonInfo),
+        #[serde(rename = "mouseUp")]
+        MouseUp(MouseButtonInfo),
+        #[serde(rename = "mouseMove")]
+        MouseMove(super::graphics::Pos),
+        #[serde(rename = "mouseWheel")]
+        MouseWheel { dx: Int, dy: Int },
         #[serde(rename = "windowSize")]
         WindowSize(super::graphics::Dim),
         #[serde(rename = "clipBoard")]
         ClipBoard(String),
+        #[serde(rename = "fileRead")]
+        FileRead { path: String, content: String },
+        #[serde(rename = "tick")]
+        Tick {
+            #[serde(rename = "elapsedMs")]
+            elapsed_ms: Nat, // since the previous tick (or, for the first, since connecting)
+        },
     }
     /// Keyboard event information.
     #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
@@ -63,6 +127,27 @@ pub mod event {
         pub ctrl: bool,
         pub meta: bool,
         pub shift: bool,
+        pub repeat: Option<bool>, // auto-repeated, from holding the key down (if known)
+    }
+    /// Mouse button event information, in window coordinates.
+    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
+    pub struct MouseButtonInfo {
+        pub pos: super::graphics::Pos,
+        pub button: MouseButton,
+    }
+    /// Mouse button.
+    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
+    pub enum MouseButton {
+        #[serde(rename = "left")]
+        Left,
+        #[serde(rename = "middle")]
+        Middle,
+        #[serde(rename = "right")]
+        Right,
+        #[serde(rename = "x1")]
+        X1,
+        #[serde(rename = "x2")]
+        X2,
     }
 }
 
@@ -75,6 +160,11 @@ pub mod graphics {
     /// Color
     pub type Color = (Nat, Nat, Nat);
 
+    /// Color from native RGB bytes.
+    pub fn color(r: u8, g: u8, b: u8) -> Color {
+        (Nat::from(r), Nat::from(g), Nat::from(b))
+    }
+
     /// (Update message's) request for graphics.
     #[derive(Debug, Clone, CandidType, Deserialize, Eq, PartialEq, Hash)]
     pub enum Request {
@@ -92,12 +182,38 @@ pub mod graphics {
         pub width: Nat,
         pub height: Nat,
     }
+    impl Dim {
+        /// Dimension from native integers.
+        pub fn new(width: u32, height: u32) -> Dim {
+            Dim {
+                width: Nat::from(width),
+                height: Nat::from(height),
+            }
+        }
+        /// Dimension as native integers, if both fit.
+        pub fn to_u32(&self) -> Option<(u32, u32)> {
+            Some((super::nat_u32(&self.width)?, super::nat_u32(&self.height)?))
+        }
+    }
     /// Position
     #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
     pub struct Pos {
         pub x: Nat,
         pub y: Nat,
     }
+    impl Pos {
+        /// Position from native integers.
+        pub fn new(x: u32, y: u32) -> Pos {
+            Pos {
+                x: Nat::from(x),
+                y: Nat::from(y),
+            }
+        }
+        /// Position as native integers, if both fit.
+        pub fn to_u32(&self) -> Option<(u32, u32)> {
+            Some((super::nat_u32(&self.x)?, super::nat_u32(&self.y)?))
+        }
+    }
     /// Rectangle
     #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
     pub struct Rect {
@@ -121,6 +237,36 @@ pub mod graphics {
         pub fill: Fill,
         pub elms: Elms,
     }
+    /// Text, drawn by the terminal in its built-in (5x5 monospace) bitmap font.
+    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
+    pub struct TextElm {
+        pub pos: Pos,
+        pub text: String,
+        pub color: Color,
+        pub zoom: Nat, // size of a glyph cell, in pixels
+    }
+    /// Bitmap, scaled to fill its rectangle.
+    ///
+    /// Without a palette, each pixel is three bytes (red, green, blue);
+    /// with one, each pixel is one byte, indexing the palette.
+    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
+    pub struct Bitmap {
+        pub rect: Rect,
+        pub width: Nat,
+        pub height: Nat,
+        #[serde(with = "serde_bytes")]
+        pub pixels: Vec<u8>, // a Candid blob
+        pub palette: Option<Vec<Color>>,
+    }
+    impl Bitmap {
+        /// Bytes per pixel, given the (lack of) palette.
+        pub fn pixel_size(&self) -> usize {
+            match self.palette {
+                None => 3,
+                Some(_) => 1,
+            }
+        }
+    }
     /// Fill
     #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
     pub enum Fill {
@@ -138,6 +284,10 @@ pub mod graphics {
         Rect(Rect, Fill),
         #[serde(rename = "node")]
         Node(Box<Node>),
+        #[serde(rename = "text")]
+        Text(TextElm),
+        #[serde(rename = "bitmap")]
+        Bitmap(Bitmap),
     }
     /// Elements
     pub type Elms = Vec<Elm>;
@@ -151,6 +301,18 @@ pub mod graphics {
         Draw(Elm),
         #[serde(rename = "redraw")]
         Redraw(NamedElms),
+        #[serde(rename = "commands")]
+        Commands(Vec<Command>),
+    }
+    /// Terminal command, from service (in an update's results; ignored in a view's).
+    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
+    pub enum Command {
+        #[serde(rename = "setClipBoard")]
+        SetClipBoard(String),
+        #[serde(rename = "setTitle")]
+        SetTitle(String),
+        #[serde(rename = "bell")]
+        Bell,
     }
     /// Result
     #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
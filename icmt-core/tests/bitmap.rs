@@ -0,0 +1,60 @@
+//! Bitmaps: decoding them from Candid (their pixels are a blob), and checking them.
+
+use candid::parser::typing::{check_prog, TypeEnv};
+use candid::{Decode, IDLArgs, IDLProg, Nat};
+
+use icmt_core::check::check_bitmap;
+use icmt_core::types::graphics::{self, Bitmap, Elm, Rect};
+
+const SERVICE_DID: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../service.did"));
+
+fn bitmap(width: u32, height: u32, pixels: &[u8], palette: Option<Vec<graphics::Color>>) -> Bitmap {
+    Bitmap {
+        rect: Rect::new(Nat::from(1), Nat::from(2), Nat::from(4), Nat::from(2)),
+        width: Nat::from(width),
+        height: Nat::from(height),
+        pixels: pixels.to_vec(),
+        palette,
+    }
+}
+
+#[test]
+fn bitmap_pixels_decode_from_a_blob() {
+    let prog: IDLProg = SERVICE_DID.parse().unwrap();
+    let mut env = TypeEnv::new();
+    check_prog(&mut env, &prog).unwrap();
+    let elm_type = env.find_type("Elm").unwrap().clone();
+    let args: IDLArgs = r#"(variant { bitmap = record {
+            rect = record { pos = record { x = 1; y = 2 }; dim = record { width = 4; height = 2 } };
+            width = 2;
+            height = 1;
+            pixels = blob "abcdef";
+            palette = null;
+        } })"#
+        .parse()
+        .unwrap();
+    let bytes = args.to_bytes_with_types(&env, &[elm_type]).unwrap();
+    let elm = Decode!(&bytes, Elm).unwrap();
+    assert_eq!(elm, Elm::Bitmap(bitmap(2, 1, b"abcdef", None)));
+}
+
+#[test]
+fn bitmap_pixel_counts_are_checked() {
+    assert_eq!(check_bitmap(&bitmap(2, 1, &[0; 6], None)), Ok(()));
+    assert!(check_bitmap(&bitmap(2, 1, &[0; 5], None)).is_err());
+    assert!(check_bitmap(&bitmap(2, 2, &[0; 6], None)).is_err());
+    let palette = Some(vec![graphics::color(0, 0, 0), graphics::color(255, 255, 255)]);
+    assert_eq!(check_bitmap(&bitmap(2, 1, &[0, 1], palette.clone())), Ok(()));
+    assert!(check_bitmap(&bitmap(2, 1, &[0, 1, 1], palette.clone())).is_err());
+    // palette indices must be within the palette.
+    assert!(check_bitmap(&bitmap(2, 1, &[0, 2], palette)).is_err());
+}
+
+#[test]
+fn oversized_bitmaps_are_rejected() {
+    let mut b = bitmap(2, 1, &[0; 6], None);
+    b.width = Nat::from(u64::from(u32::MAX) + 1);
+    assert!(check_bitmap(&b).is_err());
+    let b = bitmap(u32::MAX, u32::MAX, &[0; 6], None);
+    assert!(check_bitmap(&b).is_err());
+}
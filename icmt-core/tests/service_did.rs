@@ -0,0 +1,102 @@
+//! Check the Rust protocol types against the Candid spec in `service.did`.
+//!
+//! When either side changes without the other, this test fails and names the
+//! type path where they disagree.
+
+use std::collections::{BTreeMap, HashSet};
+
+use candid::parser::types::IDLProg;
+use candid::parser::typing::{check_prog, TypeEnv};
+use candid::types::internal::{self, Field, Type};
+use candid::CandidType;
+
+use icmt_core::types::{event, graphics};
+
+const SERVICE_DID: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../service.did"));
+
+/// Resolve type names (from the spec) and type knots (from Rust) to type structure.
+fn unroll(env: &TypeEnv, t: &Type) -> Type {
+    match t {
+        Type::Var(id) => unroll(env, env.find_type(id).unwrap()),
+        Type::Knot(_) => unroll(env, &internal::unroll(t)),
+        t => t.clone(),
+    }
+}
+
+fn check_fields(
+    env: &TypeEnv,
+    seen: &mut HashSet<(String, String)>,
+    path: &str,
+    did: &[Field],
+    rust: &[Field],
+) {
+    let did: BTreeMap<u32, &Field> = did.iter().map(|f| (f.id.get_id(), f)).collect();
+    let rust: BTreeMap<u32, &Field> = rust.iter().map(|f| (f.id.get_id(), f)).collect();
+    for (id, f) in did.iter() {
+        match rust.get(id) {
+            None => panic!("{}: field {} is in service.did, but not in Rust", path, f.id),
+            Some(rf) => check_type(env, seen, &format!("{}.{}", path, f.id), &f.ty, &rf.ty),
+        }
+    }
+    for (id, f) in rust.iter() {
+        if !did.contains_key(id) {
+            panic!("{}: field {} is in Rust, but not in service.did", path, f.id)
+        }
+    }
+}
+
+fn check_type(
+    env: &TypeEnv,
+    seen: &mut HashSet<(String, String)>,
+    path: &str,
+    did: &Type,
+    rust: &Type,
+) {
+    // Recursive types (e.g., Elm) are equal when their unrollings are; stop at revisits.
+    if !seen.insert((format!("{:?}", did), format!("{:?}", rust))) {
+        return;
+    }
+    match (unroll(env, did), unroll(env, rust)) {
+        (Type::Opt(d), Type::Opt(r)) | (Type::Vec(d), Type::Vec(r)) => {
+            check_type(env, seen, path, &d, &r)
+        }
+        (Type::Record(d), Type::Record(r)) | (Type::Variant(d), Type::Variant(r)) => {
+            check_fields(env, seen, path, &d, &r)
+        }
+        (d, r) => assert_eq!(d, r, "{}: type mismatch", path),
+    }
+}
+
+fn check_method(env: &TypeEnv, actor: &Type, name: &str, args: Vec<Type>, rets: Vec<Type>) {
+    let func = env.get_method(actor, name).unwrap();
+    assert_eq!(func.args.len(), args.len(), "{}: argument count", name);
+    assert_eq!(func.rets.len(), rets.len(), "{}: result count", name);
+    let mut seen = HashSet::new();
+    for (i, (d, r)) in func.args.iter().zip(args.iter()).enumerate() {
+        check_type(env, &mut seen, &format!("{}.arg{}", name, i), d, r);
+    }
+    for (i, (d, r)) in func.rets.iter().zip(rets.iter()).enumerate() {
+        check_type(env, &mut seen, &format!("{}.ret{}", name, i), d, r);
+    }
+}
+
+#[test]
+fn service_did_matches_rust_types() {
+    let prog: IDLProg = SERVICE_DID.parse().unwrap();
+    let mut env = TypeEnv::new();
+    let actor = check_prog(&mut env, &prog).unwrap().unwrap();
+    check_method(
+        &env,
+        &actor,
+        "view",
+        vec![graphics::Dim::ty(), Vec::<event::EventInfo>::ty()],
+        vec![graphics::Result::ty()],
+    );
+    check_method(
+        &env,
+        &actor,
+        "update",
+        vec![Vec::<event::EventInfo>::ty(), graphics::Request::ty()],
+        vec![Vec::<graphics::Result>::ty()],
+    );
+}
@@ -2,6 +2,7 @@ extern crate futures;
 extern crate garcon;
 extern crate ic_agent;
 extern crate ic_types;
+extern crate icmt_core;
 extern crate icmt_sdl2;
 extern crate num_traits;
 extern crate sdl2;
@@ -15,35 +16,21 @@ extern crate structopt;
 use structopt::StructOpt;
 
 use candid::Decode;
-use ic_agent::Agent;
-use ic_types::Principal;
-
-use candid::Nat;
-use chrono::prelude::*;
-use sdl2::event::Event as SysEvent; // not to be confused with our own definition
-use sdl2::event::WindowEvent;
-use sdl2::keyboard::Keycode;
-use sdl2::render::{Canvas, RenderTarget};
-use sdl2::surface::Surface;
 use std::fs;
-use std::fs::OpenOptions;
 use std::io;
-use std::io::Write;
 use std::path::PathBuf;
-use std::sync::mpsc;
-use std::time::Duration;
-use tokio::task;
 
 use icmt_sdl2::{
     cli::*,
-    draw::*,
+    config::{self, ConfigFile, Profile, UserConfig},
     error::*,
-    keyboard,
-    types::{
-        event, graphics, nat_ceil, skip_event, text_color, user_name, ServiceCall, UserInfoCli,
-        UserKind,
-    },
-    write::write_gifs,
+    identity,
+    network::RootKey,
+    service,
+    session::Session,
+    types::{event, UserKind},
+    window::{create_window_canvas, SdlInput, WindowSink},
+    write::FileCapture,
 };
 
 fn init_log(level_filter: log::LevelFilter) {
@@ -55,692 +42,114 @@ fn init_log(level_filter: log::LevelFilter) {
         .init();
 }
 
-const RETRY_PAUSE: Duration = Duration::from_millis(100);
-const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
-
-async fn create_agent(url: &str, pem_file: &Option<String>) -> IcmtResult<Agent> {
-    use ring::signature::Ed25519KeyPair;
-    let keypair = if let Some(pem_path) = pem_file {
-        let path = resolve_path(&pem_path)?;
-        let bytes = std::fs::read(&path)?;
-        info!("Parsing pem file {:?}", path);
-        let pem = pem::parse(&bytes)?;
-        info!("Successfully parsed pem file {:?}", path);
-        pem.contents
-    } else {
-        let rng = ring::rand::SystemRandom::new();
-        Ed25519KeyPair::generate_pkcs8(&rng)?.as_ref().to_vec()
+async fn run(cfg: ConnectCfg) -> IcmtResult<()> {
+    let capout = std::path::Path::new(&cfg.cli_opt.capture_output_path);
+    if !capout.exists() {
+        std::fs::create_dir_all(&cfg.cli_opt.capture_output_path)
+            .context(|| {
+                format!(
+                    "cannot create capture directory {:?}",
+                    cfg.cli_opt.capture_output_path
+                )
+            })
+            .with_exit_code(exit_code::CAPTURE)?;
     };
-    let identity =
-        ic_agent::identity::BasicIdentity::from_key_pair(Ed25519KeyPair::from_pkcs8(&keypair)?);
-    let agent = Agent::builder()
-        .with_url(url)
-        .with_identity(identity)
-        .build()?;
-    info!("built agent.");
-    if true {
-        // to do -- CLI switch.
-        agent.fetch_root_key().await?;
-    }
-    info!("got root key.");
-    Ok(agent)
-}
-
-fn translate_system_event(
-    video_subsystem: &sdl2::VideoSubsystem,
-    event: &SysEvent,
-) -> Option<event::Event> {
-    match event {
-        SysEvent::ClipboardUpdate { .. } => {
-            let text = match video_subsystem.clipboard().clipboard_text() {
-                Ok(text) => text,
-                Err(text) => format!("error: {}", text),
-            };
-            Some(event::Event::ClipBoard(text))
-        }
-        SysEvent::Window {
-            win_event: WindowEvent::SizeChanged(w, h),
-            ..
-        } => {
-            let dim = graphics::Dim {
-                width: Nat::from(*w as u64),
-                height: Nat::from(*h as u64),
-            };
-            Some(event::Event::WindowSize(dim))
-        }
-        SysEvent::Quit { .. }
-        | SysEvent::KeyDown {
-            keycode: Some(Keycode::Escape),
-            ..
-        } => Some(event::Event::Quit),
-        SysEvent::KeyDown {
-            keycode: Some(ref kc),
-            keymod,
-            ..
-        } => match keyboard::translate_event(kc, keymod) {
-            Some(ev) => Some(event::Event::KeyDown(vec![ev])),
-            None => None,
-        },
-        _ => None,
+    // (the session's tasks connect to a canister themselves, each with its own agent.)
+    match &cfg.service {
+        Some(service) => info!("Connecting to in-process service: {:?}", service),
+        None => info!("Connecting to IC canister: {}", cfg.canister_id),
     }
-}
-
-async fn do_redraw<'a, T1: RenderTarget>(
-    cli: &CliOpt,
-    window_dim: &graphics::Dim,
-    window_canvas: &mut Canvas<T1>,
-    file_canvas: &mut Canvas<Surface<'a>>,
-    bmp_paths: &mut Vec<String>,
-    data: &graphics::Result,
-) -> IcmtResult<()> {
-    if !cli.no_window {
-        draw(window_canvas, window_dim, data).await?;
-    }
-    if !cli.no_capture {
-        draw(file_canvas, window_dim, data).await?;
-        let path = format!(
-            "{}/screen-{}x{}-{}.bmp",
-            cli.capture_output_path,
-            window_dim.width,
-            window_dim.height,
-            Local::now().to_rfc3339()
-        );
-        file_canvas.surface().save_bmp(&path)?;
-        bmp_paths.push(path);
-    }
-    Ok(())
-}
-
-async fn do_view_task(
-    cfg: ConnectCfg,
-    remote_in: mpsc::Receiver<Option<(graphics::Dim, Vec<event::EventInfo>)>>,
-    remote_out: mpsc::Sender<graphics::Result>,
-    data_path: PathBuf,
-) -> IcmtResult<()> {
-    /* Create our own agent here since we cannot Send it here from the main thread. */
-    let canister_id = Principal::from_text(cfg.canister_id.clone()).unwrap();
-    let agent = create_agent(&cfg.replica_url, &cfg.pem_file).await?;
-    let ctx = ConnectCtx {
-        cfg: cfg.clone(),
-        canister_id,
-        agent,
-        data_path: data_path.clone(),
+    let title = match cfg.user_kind {
+        UserKind::Spectator => "IC Mini Terminal (watching)",
+        UserKind::Local(_) | UserKind::Replay(_) => "IC Mini Terminal",
     };
+    let has_input = !matches!(cfg.user_kind, UserKind::Replay(_));
+    let cli_opt = cfg.cli_opt.clone();
+    let session = Session::new(cfg);
 
-    loop {
-        let events = remote_in.recv()?;
-
-        match events {
-            None => return Ok(()),
-            Some((window_dim, events)) => {
-                let mut rr = service_call(&ctx, ServiceCall::View(window_dim, events)).await?;
-                assert_eq!(rr.len(), 1);
-                remote_out.send(rr.remove(0))?;
-            }
-        }
+    let sdl = sdl2::init()?;
+    let video_subsystem = sdl.video()?;
+    let canvas = create_window_canvas(&video_subsystem, session.dim())?;
+    let mut window = WindowSink::new(&video_subsystem, canvas, title)?;
+    window.draw = !cli_opt.no_window;
+    let mut session = session.sink(Box::new(window));
+    if !cli_opt.no_capture {
+        let capture =
+            FileCapture::new(&cli_opt, session.dim()).with_exit_code(exit_code::CAPTURE)?;
+        session = session.capture(Box::new(capture));
+    }
+    if has_input {
+        let input = SdlInput::new(&sdl, &video_subsystem, &cli_opt)?;
+        session = session.input(Box::new(input));
     }
+    session.run().await
 }
 
-async fn do_update_task(
-    cfg: ConnectCfg,
-    remote_in: mpsc::Receiver<ServiceCall>,
-    remote_out: mpsc::Sender<Vec<graphics::Result>>,
-    data_path: PathBuf,
-) -> IcmtResult<()> {
-    /* Create our own agent here since we cannot Send it here from the main thread. */
-    let canister_id = Principal::from_text(cfg.canister_id.clone()).unwrap();
-    let agent = create_agent(&cfg.replica_url, &cfg.pem_file).await?;
-    let ctx = ConnectCtx {
-        cfg,
-        canister_id,
-        agent,
-        data_path,
-    };
-    loop {
-        let sc = remote_in.recv().unwrap();
-        if let ServiceCall::FlushQuit = sc {
-            return Ok(());
-        };
-        let r = service_call(&ctx, sc).await?;
-        remote_out.send(r).unwrap();
+/// Resolve the target: the network's canister, or (with `--in-process`) the named service.
+fn resolve_target(
+    cli_opt: &CliOpt,
+    network: &NetworkOpt,
+    canister: &str,
+) -> IcmtResult<Target> {
+    match &cli_opt.in_process {
+        Some(name) => Ok(Target {
+            replica_url: format!("in-process:{}", name),
+            canister_id: canister.to_string(),
+            root_key: RootKey::Builtin,
+        }),
+        None => network.resolve(canister),
     }
 }
 
-async fn local_event_loop(ctx: ConnectCtx) -> Result<(), IcmtError> {
-    let (is_live, mut replay_events, frame_size) =
-        match (&ctx.cfg.cli_opt.command, &ctx.cfg.user_kind) {
-            (CliCommand::Replay { frame_size, .. }, UserKind::Replay(evs)) => {
-                (false, evs.clone(), frame_size.clone())
+/// Resolve the PEM file for signing calls: the given one, a dfx identity's,
+/// or else the selected identity's.
+fn identity_pem_file(
+    pem_file: Option<String>,
+    dfx_identity: Option<String>,
+) -> IcmtResult<PathBuf> {
+    match (pem_file, dfx_identity) {
+        (Some(pem_file), _) => config::resolve_path(&pem_file),
+        (None, Some(name)) => {
+            identity::check_name(&name)?;
+            let path = identity::dfx_pem_path(&name);
+            if !path.exists() {
+                return Err(IcmtError::String(format!(
+                    "no dfx identity named {:?} (expected {:?})",
+                    name, path
+                )))
+                .with_exit_code(exit_code::IDENTITY);
             }
-            (CliCommand::Connect { .. }, UserKind::Local(_)) => (true, vec![], 0),
-            _ => unreachable!(),
-        };
-
-    let mut window_dim = graphics::Dim {
-        width: Nat::from(500),
-        height: Nat::from(400),
-    }; // use CLI to init
-
-    let sdl = sdl2::init()?;
-
-    // to do --- if headless, do not do these steps; window_canvas is None
-    let video_subsystem = sdl.video()?;
-    let window = video_subsystem
-        .window(
-            "IC Mini Terminal",
-            nat_ceil(&window_dim.width),
-            nat_ceil(&window_dim.height),
-        )
-        .position_centered()
-        .resizable()
-        /*.input_grabbed() // to do -- CI flag*/
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    let mut window_canvas = window
-        .into_canvas()
-        .target_texture()
-        .present_vsync()
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    // to do --- if file-less, do not do these steps; file_canvas is None
-    let mut file_canvas = {
-        let surface = sdl2::surface::Surface::new(
-            nat_ceil(&window_dim.width),
-            nat_ceil(&window_dim.height),
-            sdl2::pixels::PixelFormatEnum::RGBA8888,
-        )?;
-        surface.into_canvas()?
-    };
-
-    let mut view_events = vec![];
-    let mut update_events = vec![];
-    let mut dump_events = vec![];
-
-    let mut dump_graphics = vec![];
-    let mut engiffen_paths = vec![];
-
-    if is_live {
-        let ev0 = skip_event(&ctx);
-        view_events = vec![];
-        dump_events = vec![ev0.clone()];
-        update_events = vec![ev0.clone()];
-    };
-
-    let (update_in, update_out) = /* Begin update task */ {
-        let cfg = ctx.cfg.clone();
-
-        // Interaction cycle as two halves (local/remote); each half is a thread.
-        // There are four end points along the cycle's halves:
-        let (local_out, remote_in) = mpsc::channel::<ServiceCall>();
-        let (remote_out, local_in) = mpsc::channel::<Vec<graphics::Result>>();
-        let data_path = PathBuf::from("out/icmt-profile-update.csv");
-
-        // 1. Remote interactions via update calls to service.
-        // (Consumes remote_in and produces remote_out).
-
-        task::spawn(do_update_task(cfg, remote_in, remote_out, data_path));
-        let req = if ctx.cfg.cli_opt.all_graphics { graphics::Request::All(window_dim.clone()) } else { graphics::Request::None };
-        if is_live {
-            local_out.send(ServiceCall::Update(update_events, req))?;
-            update_events = vec![];
+            Ok(path)
         }
-        (local_in, local_out)
-    };
-
-    let (view_in, view_out) = /* Begin view task */ {
-        let cfg = ctx.cfg.clone();
-
-        // Interaction cycle as two halves (local/remote); each half is a thread.
-        // There are four end points along the cycle's halves:
-        let (local_out, remote_in) = mpsc::channel::<Option<(graphics::Dim, Vec<event::EventInfo>)>>();
-        let (remote_out, local_in) = mpsc::channel::<graphics::Result>();
-        let data_path = PathBuf::from("out/icmt-profile-view.csv");
-
-        // 1. Remote interactions via view calls to service.
-        // (Consumes remote_in and produces remote_out).
-
-        task::spawn(do_view_task(cfg, remote_in, remote_out, data_path));
-        local_out.send(Some((window_dim.clone(), view_events)))?;
-        view_events = vec![];
-        (local_in, local_out)
-    };
-
-    let mut quit_request = false; // user has requested to quit: shut down gracefully.
-    let mut dirty_flag = true; // more events ready for view task
-    let mut ready_flag = true; // view task is ready for more events
-
-    let mut update_requests = Nat::from(1); // count update task requests (already one).
-    let mut update_ok_responses = Nat::from(0); // count update task responses (none yet).
-    let mut update_emp_responses = Nat::from(0); // count view task responses (none yet).
-
-    let mut view_requests = Nat::from(1); // count view task requests (already one).
-    let mut view_ok_responses = Nat::from(0); // count view task responses (none yet).
-    let mut view_emp_responses = Nat::from(0); // count view task responses (none yet).
-
-    let mut replay_event_counter = Nat::from(0); // count replay events replayed (none yet).
-
-    // 2. Local interactions via the SDL Event loop.
-    let mut event_pump = {
-        use sdl2::event::EventType;
-        let mut p = sdl.event_pump()?;
-        p.disable_event(EventType::FingerUp);
-        p.disable_event(EventType::FingerDown);
-        p.disable_event(EventType::FingerMotion);
-        p.disable_event(EventType::MouseMotion);
-        p
-    };
-
-    'running: loop {
-        if is_live {
-            if let Some(system_event) = event_pump.wait_event_timeout(13) {
-                {
-                    // utc/local timestamps for event
-                    let event = translate_system_event(&video_subsystem, &system_event);
-                    let event = match event {
-                        None => continue 'running,
-                        Some(event) => event,
-                    };
-                    trace!("SDL event_pump.wait_event() => {:?}", &system_event);
-                    // catch window resize event: redraw and loop:
-                    match event {
-                        event::Event::MouseDown(_) => {
-                            // ignore (for now)
-                        }
-                        event::Event::Skip => {
-                            // ignore
-                        }
-                        event::Event::Quit => {
-                            info!("Quit");
-                            println!("Begin: Quitting...");
-                            println!("Waiting for next update response...");
-                            quit_request = true;
-                        }
-                        event::Event::ClipBoard(text) => {
-                            info!("ClipBoard: {}", text);
-                            dirty_flag = true;
-                            let ev = event::EventInfo {
-                                user_info: event::UserInfo {
-                                    user_name: user_name(&ctx).unwrap(),
-                                    text_color: (
-                                        text_color(&ctx).unwrap(),
-                                        (Nat::from(0), Nat::from(0), Nat::from(0)),
-                                    ),
-                                },
-                                nonce: None,
-                                date_time_local: Local::now().to_rfc3339(),
-                                date_time_utc: Utc::now().to_rfc3339(),
-                                event: event::Event::ClipBoard(text),
-                            };
-                            view_events.push(ev.clone());
-                            dump_events.push(ev);
-                        }
-                        event::Event::WindowSize(new_dim) => {
-                            info!("WindowSize {:?}", new_dim);
-                            dirty_flag = true;
-                            let skip = skip_event(&ctx);
-                            view_events.push(skip.clone());
-                            dump_events.push(skip);
-                            write_gifs(
-                                &ctx.cfg.cli_opt,
-                                &window_dim,
-                                vec![],
-                                &vec![],
-                                &engiffen_paths,
-                            )?;
-                            engiffen_paths = vec![];
-                            window_dim = new_dim;
-                            // to do -- add event to buffer, and send to service
-                            file_canvas = {
-                                // Re-size canvas by re-creating it.
-                                let surface = sdl2::surface::Surface::new(
-                                    nat_ceil(&window_dim.width),
-                                    nat_ceil(&window_dim.height),
-                                    sdl2::pixels::PixelFormatEnum::RGBA8888,
-                                )?;
-                                surface.into_canvas()?
-                            };
-                        }
-                        event::Event::KeyDown(ref keys) => {
-                            info!("KeyDown {:?}", keys);
-                            dirty_flag = true;
-                            let ev = event::EventInfo {
-                                user_info: event::UserInfo {
-                                    user_name: user_name(&ctx).unwrap(),
-                                    text_color: (
-                                        text_color(&ctx).unwrap(),
-                                        (Nat::from(0), Nat::from(0), Nat::from(0)),
-                                    ),
-                                },
-                                nonce: None,
-                                date_time_local: Local::now().to_rfc3339(),
-                                date_time_utc: Utc::now().to_rfc3339(),
-                                event: event::Event::KeyDown(keys.clone()),
-                            };
-                            view_events.push(ev.clone());
-                            dump_events.push(ev);
-                        }
-                    }
-                }
-            }
-        } else {
-            if replay_events.len() == 0 {
-                update_out.send(ServiceCall::FlushQuit)?;
-                quit_request = true
-            } else {
-                let replay_events_now = if replay_events.len() > frame_size {
-                    let tl = replay_events.split_off(frame_size);
-                    let hd = replay_events;
-                    replay_events = tl;
-                    hd
-                } else {
-                    let r = replay_events;
-                    replay_events = vec![];
-                    r
-                };
-                replay_event_counter += replay_events_now.len();
-                info!(
-                    "Replaying {} event(s), with {} remaining (using frame_size {})...",
-                    replay_events_now.len(),
-                    replay_events.len(),
-                    frame_size
-                );
-                dump_events.extend(replay_events_now.clone());
-                update_out.send(ServiceCall::Update(
-                    replay_events_now,
-                    graphics::Request::All(window_dim.clone()),
-                ))?;
-            }
-        }
-        if quit_request {
-            write_gifs(
-                &ctx.cfg.cli_opt,
-                &window_dim,
-                dump_events,
-                &dump_graphics,
-                &engiffen_paths,
-            )?;
-            {
-                print!("Stopping view task... ");
-                view_out.send(None)?;
-                println!("Done.");
-            }
-            println!("All done.");
-            return Ok(());
-        } else
-        /* attend to view task */
-        {
-            match view_in.try_recv() {
-                Ok(rr) => {
-                    view_ok_responses += 1;
-                    info!("view_ok_responses = {}", view_ok_responses);
-
-                    do_redraw(
-                        &(ctx.cfg).cli_opt,
-                        &window_dim,
-                        &mut window_canvas,
-                        &mut file_canvas,
-                        &mut engiffen_paths,
-                        &rr,
-                    )
-                    .await?;
-
-                    ready_flag = true;
-                }
-                Err(mpsc::TryRecvError::Empty) => {
-                    view_emp_responses += 1;
-                    debug!("view_emp_responses = {}", view_emp_responses);
-                }
-                Err(e) => error!("{:?}", e),
-            };
-
-            if dirty_flag && ready_flag {
-                dirty_flag = false;
-                ready_flag = false;
-                let mut events = update_events.clone();
-                events.append(&mut (view_events.clone()));
-
-                view_out.send(Some((window_dim.clone(), events)))?;
-
-                view_requests += 1;
-                info!("view_requests = {}", view_requests);
-            }
-        };
-
-        /* attend to update task */
-        if is_live || !quit_request {
-            let update_msg = if is_live {
-                update_in.try_recv()
-            } else {
-                update_in.recv().map_err(mpsc::TryRecvError::from)
-            };
-            match update_msg {
-                Ok(graphics) => {
-                    debug!("graphics.len() = {}", graphics.len());
-                    dump_graphics.extend(graphics);
-                    update_ok_responses += 1;
-                    info!("update_ok_responses = {}", update_ok_responses);
-                    if is_live {
-                        /* send the local events in the view buffer */
-                        let req = if ctx.cfg.cli_opt.all_graphics {
-                            graphics::Request::All(window_dim.clone())
-                        } else {
-                            graphics::Request::None
-                        };
-                        update_out
-                            .send(ServiceCall::Update(view_events.clone(), req))
-                            .unwrap();
-                    };
-                    if quit_request {
-                        println!("Continue: Quitting...");
-                        println!("Waiting for final update-task response.");
-                        match update_in.try_recv() {
-                            Ok(graphics) => {
-                                info!("graphics.len() = {}", graphics.len());
-                                dump_graphics.extend(graphics);
-                                update_out.send(ServiceCall::FlushQuit)?;
-                                println!("Done.");
-                            }
-                            Err(e) => return Err(IcmtError::String(e.to_string())),
-                        }
-                    };
-                    update_requests += 1;
-                    info!("update_requests = {}", update_requests);
-                    update_events = view_events;
-                    view_events = vec![];
-                    dirty_flag = true;
-                }
-                Err(mpsc::TryRecvError::Empty) => {
-                    update_emp_responses += 1;
-                    debug!("update_emp_responses = {}", update_emp_responses);
-                    /* Update task not ready */
-                    if quit_request {
-                        println!("Continue: Quitting...");
-                        println!("Waiting for final update-task response.");
-                        let graphics = update_in.recv()?;
-                        info!("graphics.len() = {}", graphics.len());
-                        dump_graphics.extend(graphics);
-                        update_out.send(ServiceCall::FlushQuit)?;
-                        println!("Done.");
-                    } else {
-                        /* not ready; do nothing */
-                    }
-                }
-                Err(e) => {
-                    error!("Update task error: {:?}", e);
-                    println!("Cannot recover; quiting...");
-                    quit_request = true;
-                }
-            }
-        };
-
-        // attend to next batch of local events, and loop everything above
-        continue 'running;
+        (None, None) => identity::selected_pem_path(),
     }
 }
 
-// to do -- fix hack; refactor to remove Option<_> in return type
-pub async fn service_call(
-    ctx: &ConnectCtx,
-    call: ServiceCall,
-) -> IcmtResult<Vec<graphics::Result>> {
-    if let ServiceCall::FlushQuit = call {
-        return Ok(vec![]);
-    };
-    let mut data_file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(&ctx.data_path)
-        .expect(&format!("Failed to open {:?}", &ctx.data_path));
-    let prefix = match &call {
-        ServiceCall::FlushQuit => unreachable!(),
-        ServiceCall::View { .. } => "Service (view):",
-        ServiceCall::Update { .. } => "Service (update):",
-    };
-    debug!(
-        "{}: to canister_id {:?} at replica_url {:?}",
-        prefix, ctx.cfg.canister_id, ctx.cfg.replica_url
-    );
-    let delay = garcon::Delay::builder()
-        .throttle(RETRY_PAUSE)
-        .timeout(REQUEST_TIMEOUT)
-        .build();
-    let timestamp = std::time::SystemTime::now();
-    let arg_bytes = match call.clone() {
-        ServiceCall::FlushQuit => candid::encode_args(()).unwrap(),
-        ServiceCall::View(window_dim, evs) => candid::encode_args((window_dim, evs)).unwrap(),
-        ServiceCall::Update(evs, req) => candid::encode_args((evs, req)).unwrap(),
-    };
-    info!(
-        "{}: Encoded argument via Candid; Arg size {:?} bytes",
-        prefix,
-        arg_bytes.len()
-    );
-    info!("{}: Awaiting response from service...", prefix);
-    // do an update or query call, based on the ServiceCall case:
-    let blob_res = match call.clone() {
-        ServiceCall::FlushQuit => None,
-        ServiceCall::View(_window_dim, _keys) => {
-            let resp = ctx
-                .agent
-                .query(&ctx.canister_id, "view")
-                .with_arg(arg_bytes)
-                .call()
-                .await?;
-            Some(resp)
-        }
-        ServiceCall::Update(_keys, _gfx_req) => {
-            let resp = ctx
-                .agent
-                .update(&ctx.canister_id, "update")
-                .with_arg(arg_bytes)
-                .call_and_wait(delay)
-                .await?;
-            Some(resp)
-        }
-    };
-    let call_elapsed = timestamp.elapsed().unwrap();
-    if let Some(blob_res) = blob_res {
-        info!(
-            "{}: Ok: Response size {:?} bytes; elapsed time {:?}",
-            prefix,
-            blob_res.len(),
-            call_elapsed
-        );
-        let decode_size = blob_res.len();
-        let decode_begin = std::time::SystemTime::now();
-        match call.clone() {
-            ServiceCall::FlushQuit => Ok(vec![]),
-            ServiceCall::Update(_, _) => match candid::Decode!(&(*blob_res), Vec<graphics::Result>)
-            {
-                Ok(res) => Ok(res),
-                Err(candid_err) => {
-                    error!("{}: Candid decoding error: {:?}", prefix, candid_err);
-                    Err(IcmtError::String("decoding error".to_string()))
-                }
-            },
-            ServiceCall::View(_, _) => match candid::Decode!(&(*blob_res), graphics::Result) {
-                Ok(res) => {
-                    if ctx.cfg.cli_opt.log_trace {
-                        let mut res_log = format!("{:?}", &res);
-                        if res_log.len() > 1000 {
-                            res_log.truncate(1000);
-                            res_log.push_str("...(truncated)");
-                        }
-                        trace!(
-                            "{}: Successful decoding of graphics output: {:?}",
-                            prefix,
-                            res_log
-                        );
-                    }
-                    let decode_elapsed = decode_begin.elapsed().unwrap();
-                    info!(
-                        "{:?} bytes of Candid decoded in elapsed time {:?}",
-                        decode_size, decode_elapsed
-                    );
-                    data_file.write_all(
-                        format!(
-                            "{}, {}, {}, {}\n",
-                            Local::now().to_rfc3339(),
-                            call_elapsed.as_nanos(),
-                            decode_size,
-                            decode_elapsed.as_nanos(),
-                        )
-                        .as_bytes(),
-                    )?;
-                    data_file.sync_all()?;
-                    Ok(vec![res])
-                }
-                Err(candid_err) => {
-                    error!("{}: Candid decoding error: {:?}", prefix, candid_err);
-                    Err(IcmtError::String("decoding error".to_string()))
-                }
-            },
+#[tokio::main]
+async fn main() {
+    if let Err(e) = cli_main().await {
+        eprintln!("ic-mt: error: {}", e);
+        let mut source = std::error::Error::source(&e);
+        while let Some(e) = source {
+            eprintln!("  caused by: {}", e);
+            source = e.source();
         }
-    } else {
-        error!(
-            "{}: Error result: {:?}; elapsed time {:?}",
-            prefix, blob_res, call_elapsed
-        );
-        Err(IcmtError::String("ic-mt error".to_string()))
+        std::process::exit(e.exit_code());
     }
 }
 
-async fn run(cfg: ConnectCfg) -> IcmtResult<()> {
-    let capout = std::path::Path::new(&cfg.cli_opt.capture_output_path);
-    if !capout.exists() {
-        std::fs::create_dir_all(&cfg.cli_opt.capture_output_path)?;
-    };
-    let canister_id = Principal::from_text(cfg.canister_id.clone()).unwrap();
-    let agent = create_agent(&cfg.replica_url, &cfg.pem_file).await?;
-
-    info!("Connecting to IC canister: {}", canister_id);
-    let ctx = ConnectCtx {
-        cfg,
-        canister_id,
-        agent,
-        data_path: PathBuf::from("."),
+async fn cli_main() -> IcmtResult<()> {
+    let matches = CliOpt::clap().get_matches();
+    let mut cli_opt = CliOpt::from_clap(&matches);
+    let legacy_replica_url = cli_opt.apply_legacy_replica_url()?;
+    let profile = match &cli_opt.command {
+        CliCommand::Connect {
+            profile: Some(name),
+            ..
+        } => ConfigFile::load()?.profile(name)?.clone(),
+        _ => Profile::default(),
     };
-    trace!("{:?}", ctx.cfg);
-
-    local_event_loop(ctx).await?;
-    Ok(())
-}
-
-pub fn resolve_path(file: &str) -> IcmtResult<PathBuf> {
-    let file = PathBuf::from(shellexpand::tilde(file).into_owned());
-    if file.is_absolute() {
-        Ok(file)
-    } else {
-        let base = std::env::current_dir()?;
-        Ok(base.join(file))
-    }
-}
-
-#[tokio::main]
-async fn main() -> IcmtResult<()> {
-    let cli_opt = CliOpt::from_args();
+    cli_opt.apply_profile(&profile, &matches)?;
     init_log(
         match (cli_opt.log_trace, cli_opt.log_debug, cli_opt.log_info) {
             (true, _, _) => log::LevelFilter::Trace,
@@ -749,7 +158,17 @@ async fn main() -> IcmtResult<()> {
             (_, _, _) => log::LevelFilter::Warn,
         },
     );
+    if let Some(url) = legacy_replica_url {
+        warn!(
+            "A replica URL before the canister is deprecated; use --network {}",
+            url
+        );
+    }
     info!("Evaluating CLI command: {:?} ...", &cli_opt.command);
+    let in_process = match &cli_opt.in_process {
+        Some(name) => Some(service::in_process(name)?),
+        None => None,
+    };
     let c = cli_opt.command.clone();
     let () = match c {
         CliCommand::Completions { shell: s } => {
@@ -757,43 +176,113 @@ async fn main() -> IcmtResult<()> {
             CliOpt::clap().gen_completions_to("icmt", s, &mut io::stdout());
             info!("done");
         }
+        CliCommand::Whoami {
+            pem_file,
+            dfx_identity,
+        } => {
+            let pem_file = identity_pem_file(pem_file, dfx_identity)?;
+            let identity = identity::load(&pem_file)?;
+            println!("{}", identity::principal(&identity)?);
+        }
+        CliCommand::Identity { command } => match command {
+            IdentityCommand::New { name } => {
+                let path = identity::create(&name)?;
+                println!("Created identity {:?} at {:?}", name, path);
+            }
+            IdentityCommand::List => {
+                let selected = identity::selected()?;
+                for name in identity::list()? {
+                    let mark = if name == selected { "*" } else { " " };
+                    println!("{} {}", mark, name);
+                }
+            }
+            IdentityCommand::Use { name } => {
+                identity::select(&name)?;
+                println!("Using identity {:?}", name);
+            }
+        },
         CliCommand::Replay {
             canister_id,
-            replica_url,
+            network,
             events_file_path,
             ..
         } => {
-            let events_hex = fs::read_to_string(events_file_path)?;
-            let events_bin = hex::decode(&events_hex)?;
-            let events = Decode!(&events_bin, Vec<event::EventInfo>)?;
+            let target = resolve_target(&cli_opt, &network, &canister_id)?;
+            let events = fs::read_to_string(&events_file_path)
+                .map_err(IcmtError::from)
+                .and_then(|events_hex| Ok(hex::decode(events_hex.trim())?))
+                .and_then(|events_bin| Ok(Decode!(&events_bin, Vec<event::EventInfo>)?))
+                .context(|| format!("cannot read events file {:?}", events_file_path))
+                .with_exit_code(exit_code::IO)?;
             let user_kind = UserKind::Replay(events);
             let cfg = ConnectCfg {
-                canister_id,
-                replica_url,
+                canister_id: target.canister_id,
+                replica_url: target.replica_url,
+                root_key: target.root_key,
                 cli_opt,
                 user_kind,
-                pem_file: None,
+                pem_file: Some(identity_pem_file(None, None)?),
+                send_files: vec![],
+                service: in_process,
+            };
+            run(cfg).await?;
+        }
+        CliCommand::Watch {
+            canister_id,
+            network,
+            pem_file,
+            dfx_identity,
+            ..
+        } => {
+            let target = resolve_target(&cli_opt, &network, &canister_id)?;
+            let cfg = ConnectCfg {
+                canister_id: target.canister_id,
+                replica_url: target.replica_url,
+                root_key: target.root_key,
+                cli_opt,
+                user_kind: UserKind::Spectator,
+                pem_file: Some(identity_pem_file(pem_file, dfx_identity)?),
+                send_files: vec![],
+                service: in_process,
             };
             run(cfg).await?;
         }
         CliCommand::Connect {
             canister_id,
-            replica_url,
+            network,
             pem_file,
+            dfx_identity,
+            send_files,
+            user_name,
+            fg_color,
+            bg_color,
+            ..
         } => {
-            let user_info: UserInfoCli = {
-                (
-                    format!("Guest-{}", Local::now().to_rfc3339()),
-                    (Nat::from(255), Nat::from(255), Nat::from(255)),
-                )
+            let canister_id = canister_id.ok_or_else(|| {
+                IcmtError::String("no canister; give one, or a profile with one".to_string())
+            })?;
+            let target = resolve_target(&cli_opt, &network, &canister_id)?;
+            let remembered = UserConfig::remember(user_name.clone(), fg_color, bg_color)?;
+            // The profile's user name and colors are for this session, not remembered.
+            let profile_fg_color = profile.fg_color.as_deref().map(parse_color).transpose()?;
+            let profile_bg_color = profile.bg_color.as_deref().map(parse_color).transpose()?;
+            let user = UserConfig {
+                user_name: user_name
+                    .or_else(|| profile.user_name.clone())
+                    .unwrap_or(remembered.user_name),
+                fg_color: fg_color.or(profile_fg_color).unwrap_or(remembered.fg_color),
+                bg_color: bg_color.or(profile_bg_color).unwrap_or(remembered.bg_color),
             };
-            let user_kind = UserKind::Local(user_info);
+            let user_kind = UserKind::Local(user.user_info_cli());
             let cfg = ConnectCfg {
-                canister_id,
-                replica_url,
+                canister_id: target.canister_id,
+                replica_url: target.replica_url,
+                root_key: target.root_key,
                 cli_opt,
                 user_kind,
-                pem_file,
+                pem_file: Some(identity_pem_file(pem_file, dfx_identity)?),
+                send_files,
+                service: in_process,
             };
             run(cfg).await?;
         }
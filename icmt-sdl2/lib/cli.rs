@@ -3,10 +3,14 @@
 use clap::Shell;
 use structopt::StructOpt;
 
-use ic_agent::Agent;
-use ic_types::Principal;
+use crate::config::Profile;
+use crate::dfx;
+use crate::error::IcmtResult;
+use crate::network::{self, Network, RootKey};
+use crate::service::Service;
 
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Internet Computer Mini Terminal (ic-mt)
 #[derive(StructOpt, Debug, Clone)]
@@ -27,6 +31,36 @@ pub struct CliOpt {
     /// Dump all graphics for updates; for generating replay tests.
     #[structopt(short = "G", long = "all-graphics")]
     pub all_graphics: bool,
+    /// Initial window size, as `WIDTHxHEIGHT`.
+    #[structopt(long = "window-size", parse(try_from_str = "parse_dim"))]
+    pub window_size: Option<(u32, u32)>,
+    /// Keymap file (RON) that names keys and chooses the quit key.
+    #[structopt(short = "k", long = "keymap")]
+    pub keymap: Option<String>,
+    /// Suppress auto-repeated key presses (from holding a key down).
+    #[structopt(long = "no-repeat")]
+    pub no_repeat: bool,
+    /// Name printable keys by their US layout, rather than by the text that they input.
+    #[structopt(long = "no-text-input")]
+    pub no_text_input: bool,
+    /// Send a tick event (and query the view) at this interval, in milliseconds, even without input.
+    #[structopt(long = "tick-ms", parse(try_from_str = "parse_tick_ms"))]
+    pub tick_ms: Option<u64>,
+    /// Minimum interval between mouse motion events sent to the service, in milliseconds
+    /// (the latest motion within an interval is sent when it ends).
+    #[structopt(long = "mouse-motion-ms", default_value = "50")]
+    pub mouse_motion_ms: u64,
+    /// Most update calls in flight at once, with services that do events in nonce order
+    /// (others get one at a time; see `--canister-nonces`).
+    #[structopt(long = "max-updates", default_value = "1")]
+    pub max_updates: usize,
+    /// The canister does each event at most once, in nonce order: failed update calls are
+    /// sent again, and `--max-updates` calls can be in flight.
+    #[structopt(long = "canister-nonces")]
+    pub canister_nonces: bool,
+    /// Use an in-process service (`counter`) rather than a canister; for testing without a replica.
+    #[structopt(long = "in-process")]
+    pub in_process: Option<String>,
     /// Trace-level logging (most verbose)
     #[structopt(short = "t", long = "trace-log")]
     pub log_trace: bool,
@@ -49,31 +83,309 @@ pub enum CliCommand {
     Completions { shell: Shell },
     #[structopt(name = "connect", about = "Connect to an IC canister.")]
     Connect {
-        replica_url: String,
+        /// Canister ID, or canister name in the current dfx project (or else, the profile's).
+        canister_id: Option<String>,
+        /// Canister, after a replica URL (`connect <replica_url> <canister_id>`; deprecated).
+        #[structopt(raw(hidden = "true"))]
+        legacy_canister_id: Option<String>,
+        /// Apply a named profile from the config file (~/.config/ic-mt/config.toml).
+        #[structopt(short = "P", long = "profile")]
+        profile: Option<String>,
+        #[structopt(flatten)]
+        network: NetworkOpt,
+        #[structopt(short = "p", long = "pem-file")]
+        pem_file: Option<String>,
+        /// Use a dfx identity, by name (from ~/.config/dfx/identity).
+        #[structopt(long = "identity", conflicts_with = "pem_file")]
+        dfx_identity: Option<String>,
+        /// Send the content of a file to the canister (as a fileRead event), once connected.
+        #[structopt(short = "f", long = "send-file")]
+        send_files: Vec<String>,
+        /// User name for events (remembered for later sessions).
+        #[structopt(short = "u", long = "user-name")]
+        user_name: Option<String>,
+        /// Foreground (text) color for events, as `r,g,b` or `#rrggbb` (remembered).
+        #[structopt(long = "fg-color", parse(try_from_str = "parse_color"))]
+        fg_color: Option<(u8, u8, u8)>,
+        /// Background color for events, as `r,g,b` or `#rrggbb` (remembered).
+        #[structopt(long = "bg-color", parse(try_from_str = "parse_color"))]
+        bg_color: Option<(u8, u8, u8)>,
+    },
+    #[structopt(
+        name = "watch",
+        about = "Watch an IC canister's view, without sending input."
+    )]
+    Watch {
+        /// Canister ID, or canister name in the current dfx project.
         canister_id: String,
+        /// Canister, after a replica URL (`watch <replica_url> <canister_id>`; deprecated).
+        #[structopt(raw(hidden = "true"))]
+        legacy_canister_id: Option<String>,
+        #[structopt(flatten)]
+        network: NetworkOpt,
         #[structopt(short = "p", long = "pem-file")]
         pem_file: Option<String>,
+        /// Use a dfx identity, by name (from ~/.config/dfx/identity).
+        #[structopt(long = "identity", conflicts_with = "pem_file")]
+        dfx_identity: Option<String>,
+        /// Interval between view queries, in milliseconds (at least 100).
+        #[structopt(
+            short = "i",
+            long = "interval-ms",
+            default_value = "500",
+            parse(try_from_str = "parse_watch_ms")
+        )]
+        interval_ms: u64,
+    },
+    #[structopt(
+        name = "whoami",
+        about = "Print the principal of the identity used for calls."
+    )]
+    Whoami {
+        #[structopt(short = "p", long = "pem-file")]
+        pem_file: Option<String>,
+        /// Use a dfx identity, by name (from ~/.config/dfx/identity).
+        #[structopt(long = "identity", conflicts_with = "pem_file")]
+        dfx_identity: Option<String>,
+    },
+    #[structopt(name = "identity", about = "Manage identities for calls.")]
+    Identity {
+        #[structopt(subcommand)]
+        command: IdentityCommand,
     },
     #[structopt(
         name = "replay",
         about = "Replay captured events as if they were live."
     )]
     Replay {
-        replica_url: String,
+        /// Canister ID, or canister name in the current dfx project.
         canister_id: String,
+        #[structopt(flatten)]
+        network: NetworkOpt,
         events_file_path: String,
+        /// Events file, after a replica URL and a canister
+        /// (`replay <replica_url> <canister_id> <events_file_path>`; deprecated).
+        #[structopt(raw(hidden = "true"))]
+        legacy_events_file_path: Option<String>,
         /// Frame size, in number of events, for the replay's update requests.
         #[structopt(short = "s", long = "frame-size", default_value = "6")]
         frame_size: usize,
     },
 }
 
-/// Connection context: IC agent object, for server calls, and configuration info.
-pub struct ConnectCtx {
-    pub cfg: ConnectCfg,
-    pub agent: Agent,
-    pub canister_id: Principal,
-    pub data_path: PathBuf,
+/// Network to connect to, and how to trust its root key.
+#[derive(StructOpt, Debug, Clone)]
+pub struct NetworkOpt {
+    /// Network: `local` (default), `ic`, a network named in dfx.json, or a replica URL.
+    #[structopt(short = "n", long = "network")]
+    pub network: Option<Network>,
+    /// Pinned root key file (DER, raw or hex), rather than the IC's or a fetched one.
+    #[structopt(long = "root-key")]
+    pub root_key: Option<String>,
+    /// Fetch the root key from a replica that is not local (insecure: trusts the replica).
+    #[structopt(long = "insecure-fetch-root-key")]
+    pub insecure_fetch_root_key: bool,
+}
+
+/// Resolved connection target.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub replica_url: String,
+    pub canister_id: String,
+    pub root_key: RootKey,
+}
+
+impl NetworkOpt {
+    /// Replica URL, canister ID (from an ID or a dfx project's canister name),
+    /// and how to trust the root key.
+    pub fn resolve(&self, canister: &str) -> IcmtResult<Target> {
+        let network = self.network.clone().unwrap_or(Network::Local);
+        let (replica_url, network, canister_id) = dfx::resolve(&network, canister)?;
+        let root_key =
+            network::root_key(&network, &self.root_key, self.insecure_fetch_root_key)?;
+        Ok(Target {
+            replica_url,
+            canister_id,
+            root_key,
+        })
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum IdentityCommand {
+    #[structopt(name = "new", about = "Generate a new identity.")]
+    New { name: String },
+    #[structopt(name = "list", about = "List identities; the selected one is marked with *.")]
+    List,
+    #[structopt(name = "use", about = "Select an identity for later sessions.")]
+    Use { name: String },
+}
+
+impl CliOpt {
+    /// Accept a replica URL as the first positional argument, as before `--network`
+    /// (deprecated); returns the URL, if there was one.
+    pub fn apply_legacy_replica_url(&mut self) -> Result<Option<String>, String> {
+        let (replica_url, network) = match &mut self.command {
+            CliCommand::Connect {
+                canister_id,
+                legacy_canister_id: Some(id),
+                network,
+                ..
+            } => (canister_id.replace(id.clone()), network),
+            CliCommand::Watch {
+                canister_id,
+                legacy_canister_id: Some(id),
+                network,
+                ..
+            } => (Some(std::mem::replace(canister_id, id.clone())), network),
+            CliCommand::Replay {
+                canister_id,
+                events_file_path,
+                legacy_events_file_path: Some(path),
+                network,
+                ..
+            } => {
+                let id = std::mem::replace(events_file_path, path.clone());
+                (Some(std::mem::replace(canister_id, id)), network)
+            }
+            _ => return Ok(None),
+        };
+        let replica_url = replica_url.unwrap();
+        if network.network.is_some() {
+            return Err(format!(
+                "replica URL {:?} given with --network; give only --network",
+                replica_url
+            ));
+        }
+        network.network = Some(replica_url.parse()?);
+        Ok(Some(replica_url))
+    }
+
+    /// Apply a profile, for the options that the command line does not give
+    /// (except the user name and colors, which `connect` applies without remembering them).
+    pub fn apply_profile(
+        &mut self,
+        profile: &Profile,
+        matches: &clap::ArgMatches,
+    ) -> Result<(), String> {
+        if matches.occurrences_of("capture_output_path") == 0 {
+            if let Some(out) = &profile.out {
+                self.capture_output_path = out.clone();
+            }
+        }
+        if matches.occurrences_of("engiffen_frame_rate") == 0 {
+            if let Some(frame_rate) = profile.engiffen_frame_rate {
+                self.engiffen_frame_rate = frame_rate;
+            }
+        }
+        if matches.occurrences_of("no_capture") == 0 {
+            if let Some(no_capture) = profile.no_capture {
+                self.no_capture = no_capture;
+            }
+        }
+        if matches.occurrences_of("all_graphics") == 0 {
+            if let Some(all_graphics) = profile.all_graphics {
+                self.all_graphics = all_graphics;
+            }
+        }
+        if matches.occurrences_of("canister_nonces") == 0 {
+            if let Some(canister_nonces) = profile.canister_nonces {
+                self.canister_nonces = canister_nonces;
+            }
+        }
+        if self.keymap.is_none() {
+            self.keymap = profile.keymap.clone();
+        }
+        if self.window_size.is_none() {
+            self.window_size = profile.window_size.as_deref().map(parse_dim).transpose()?;
+        }
+        if let CliCommand::Connect {
+            canister_id,
+            network,
+            pem_file,
+            dfx_identity,
+            ..
+        } = &mut self.command
+        {
+            if canister_id.is_none() {
+                *canister_id = profile.canister.clone();
+            }
+            if network.network.is_none() {
+                network.network = profile.network.as_deref().map(str::parse).transpose()?;
+            }
+            if pem_file.is_none() && dfx_identity.is_none() {
+                *pem_file = profile.pem_file.clone();
+                *dfx_identity = profile.identity.clone();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a size, as `WIDTHxHEIGHT`.
+pub fn parse_dim(s: &str) -> Result<(u32, u32), String> {
+    let err = || format!("invalid size {:?}; expected WIDTHxHEIGHT", s);
+    let mut parts = s.splitn(2, 'x');
+    match (parts.next(), parts.next()) {
+        (Some(w), Some(h)) => Ok((
+            w.trim().parse().map_err(|_| err())?,
+            h.trim().parse().map_err(|_| err())?,
+        )),
+        _ => Err(err()),
+    }
+}
+
+/// Shortest tick interval, in milliseconds; shorter ones would flood the service with events.
+pub const MIN_TICK_MS: u64 = 10;
+
+/// Shortest interval between a spectator's view queries, in milliseconds; shorter ones
+/// would flood the replica with queries.
+pub const MIN_WATCH_MS: u64 = 100;
+
+/// Parse a tick interval, in milliseconds (at least `MIN_TICK_MS`).
+pub fn parse_tick_ms(s: &str) -> Result<u64, String> {
+    parse_interval_ms("tick", MIN_TICK_MS, s)
+}
+
+/// Parse an interval between view queries, in milliseconds (at least `MIN_WATCH_MS`).
+pub fn parse_watch_ms(s: &str) -> Result<u64, String> {
+    parse_interval_ms("watch", MIN_WATCH_MS, s)
+}
+
+fn parse_interval_ms(what: &str, min_ms: u64, s: &str) -> Result<u64, String> {
+    match s.trim().parse::<u64>() {
+        Ok(ms) if ms >= min_ms => Ok(ms),
+        Ok(ms) => Err(format!(
+            "{} interval {} ms is too short; expected at least {} ms",
+            what, ms, min_ms
+        )),
+        Err(_) => Err(format!(
+            "invalid {} interval {:?}; expected milliseconds",
+            what, s
+        )),
+    }
+}
+
+/// Parse a color, as `r,g,b` (decimal bytes) or `#rrggbb` (hex).
+pub fn parse_color(s: &str) -> Result<(u8, u8, u8), String> {
+    let err = || format!("invalid color {:?}; expected r,g,b or #rrggbb", s);
+    if let Some(hex) = s.strip_prefix('#') {
+        let bytes = hex::decode(hex).map_err(|_| err())?;
+        match bytes.as_slice() {
+            [r, g, b] => Ok((*r, *g, *b)),
+            _ => Err(err()),
+        }
+    } else {
+        let parts: Vec<&str> = s.split(',').map(|p| p.trim()).collect();
+        match parts.as_slice() {
+            [r, g, b] => Ok((
+                r.parse().map_err(|_| err())?,
+                g.parse().map_err(|_| err())?,
+                b.parse().map_err(|_| err())?,
+            )),
+            _ => Err(err()),
+        }
+    }
 }
 
 /// Connection configuration
@@ -82,6 +394,12 @@ pub struct ConnectCfg {
     pub cli_opt: CliOpt,
     pub canister_id: String,
     pub replica_url: String,
+    pub root_key: RootKey,
     pub user_kind: crate::types::UserKind,
-    pub pem_file: Option<String>,
+    /// PEM file of the identity that signs calls; without one, the selected identity's.
+    pub pem_file: Option<PathBuf>,
+    pub send_files: Vec<String>,
+    /// Service backend, shared by the session's calls; without one, each kind of call
+    /// connects to the IC canister with its own agent.
+    pub service: Option<Arc<dyn Service>>,
 }
@@ -0,0 +1,170 @@
+//! Per-user configuration, remembered across sessions.
+
+use crate::error::{Context, IcmtError, IcmtResult};
+use crate::types::{graphics, UserInfoCli};
+
+use chrono::prelude::*;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Directory for per-user configuration: `$ICMT_CONFIG_DIR`, or else `~/.config/ic-mt`.
+pub fn config_dir() -> PathBuf {
+    match std::env::var("ICMT_CONFIG_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(shellexpand::tilde("~/.config/ic-mt").into_owned()),
+    }
+}
+
+/// Resolve a file path: expand `~`, and make it absolute (from the current directory).
+pub fn resolve_path(file: &str) -> IcmtResult<PathBuf> {
+    let file = PathBuf::from(shellexpand::tilde(file).into_owned());
+    if file.is_absolute() {
+        Ok(file)
+    } else {
+        let base = std::env::current_dir()?;
+        Ok(base.join(file))
+    }
+}
+
+/// User name and colors, remembered so that per-user views stay stable across sessions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserConfig {
+    pub user_name: String,
+    pub fg_color: (u8, u8, u8),
+    pub bg_color: (u8, u8, u8),
+}
+
+impl UserConfig {
+    pub fn path() -> PathBuf {
+        config_dir().join("user.ron")
+    }
+
+    /// Load the user configuration, if there is one.
+    pub fn load() -> IcmtResult<Option<UserConfig>> {
+        let path = UserConfig::path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&path)
+            .context(|| format!("cannot read user config {:?}", path))?;
+        let user = ron::de::from_str(&text)
+            .map_err(|e| IcmtError::String(format!("user config {:?}: {}", path, e)))?;
+        Ok(Some(user))
+    }
+
+    /// The saved user configuration, with these settings (e.g., from the command line) in
+    /// place of the saved ones, and saved for later sessions if they changed it.
+    ///
+    /// Without saved settings, the user is a guest, with white text on black.
+    pub fn remember(
+        user_name: Option<String>,
+        fg_color: Option<(u8, u8, u8)>,
+        bg_color: Option<(u8, u8, u8)>,
+    ) -> IcmtResult<UserConfig> {
+        let saved = UserConfig::load()?;
+        let remembered = UserConfig {
+            user_name: user_name
+                .or_else(|| saved.as_ref().map(|u| u.user_name.clone()))
+                .unwrap_or_else(|| format!("Guest-{}", Local::now().to_rfc3339())),
+            fg_color: fg_color
+                .or_else(|| saved.as_ref().map(|u| u.fg_color))
+                .unwrap_or((255, 255, 255)),
+            bg_color: bg_color
+                .or_else(|| saved.as_ref().map(|u| u.bg_color))
+                .unwrap_or((0, 0, 0)),
+        };
+        if saved.as_ref() != Some(&remembered) {
+            remembered.save()?;
+        }
+        Ok(remembered)
+    }
+
+    pub fn save(&self) -> IcmtResult<()> {
+        let path = UserConfig::path();
+        std::fs::create_dir_all(config_dir())
+            .context(|| format!("cannot create config directory {:?}", config_dir()))?;
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| IcmtError::String(format!("user config {:?}: {}", path, e)))?;
+        std::fs::write(&path, text).context(|| format!("cannot write user config {:?}", path))?;
+        info!("Saved user config to {:?}", path);
+        Ok(())
+    }
+
+    pub fn user_info_cli(&self) -> UserInfoCli {
+        let (r, g, b) = self.fg_color;
+        let fg_color = graphics::color(r, g, b);
+        let (r, g, b) = self.bg_color;
+        let bg_color = graphics::color(r, g, b);
+        UserInfoCli {
+            user_name: self.user_name.clone(),
+            fg_color,
+            bg_color,
+        }
+    }
+}
+
+/// Named connection profile, from the config file.
+///
+/// Each field is optional; command-line flags override the profile's values.
+/// The user name and colors are only for the profile's sessions (not remembered).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// `local`, `ic`, a network named in dfx.json, or a replica URL.
+    pub network: Option<String>,
+    /// Canister ID, or canister name in the dfx project.
+    pub canister: Option<String>,
+    /// dfx identity name.
+    pub identity: Option<String>,
+    pub pem_file: Option<String>,
+    pub user_name: Option<String>,
+    /// As `r,g,b` or `#rrggbb`.
+    pub fg_color: Option<String>,
+    pub bg_color: Option<String>,
+    /// As `WIDTHxHEIGHT`.
+    pub window_size: Option<String>,
+    pub out: Option<String>,
+    pub no_capture: Option<bool>,
+    pub all_graphics: Option<bool>,
+    /// The canister dedupes and orders events by nonce (see `--canister-nonces`).
+    pub canister_nonces: Option<bool>,
+    pub engiffen_frame_rate: Option<usize>,
+    pub keymap: Option<String>,
+}
+
+/// Config file (TOML), with named profiles, as `[profiles.<name>]` tables.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ConfigFile {
+    pub fn path() -> PathBuf {
+        config_dir().join("config.toml")
+    }
+
+    /// Load the config file; without one, there are no profiles.
+    pub fn load() -> IcmtResult<ConfigFile> {
+        let path = ConfigFile::path();
+        if !path.exists() {
+            return Ok(ConfigFile::default());
+        }
+        let text =
+            std::fs::read_to_string(&path).context(|| format!("cannot read config {:?}", path))?;
+        toml::from_str(&text).map_err(|e| IcmtError::String(format!("config {:?}: {}", path, e)))
+    }
+
+    pub fn profile(&self, name: &str) -> IcmtResult<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            IcmtError::String(format!(
+                "no profile named {:?} in {:?}",
+                name,
+                ConfigFile::path()
+            ))
+        })
+    }
+}
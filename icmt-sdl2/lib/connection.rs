@@ -0,0 +1,154 @@
+//! Connection state, and retrying service calls with exponential backoff.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Which of the service's calls a state is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    View,
+    Update,
+}
+
+/// State of the connection to the service, as shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// Retrying after a transient error; `attempt` counts from 1.
+    Retrying { attempt: u32, error: String },
+    /// Gave up, after a permanent error (or too many transient ones).
+    Failed(String),
+}
+
+impl ConnectionState {
+    /// Short label, without error details (e.g., for the window title).
+    pub fn label(&self) -> String {
+        match self {
+            ConnectionState::Connecting => "connecting".to_string(),
+            ConnectionState::Connected => "connected".to_string(),
+            ConnectionState::Retrying { attempt, .. } => format!("retrying ({})", attempt),
+            ConnectionState::Failed(_) => "failed".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectionState::Retrying { attempt, error } => {
+                write!(f, "retrying (attempt {}) after error: {}", attempt, error)
+            }
+            ConnectionState::Failed(error) => write!(f, "failed: {}", error),
+            state => write!(f, "{}", state.label()),
+        }
+    }
+}
+
+/// Connection states of the view and update calls.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub view: ConnectionState,
+    pub update: ConnectionState,
+    /// Events of failed update calls that were not sent again (the service may or may
+    /// not have done them).
+    pub unconfirmed: usize,
+}
+
+impl Connection {
+    pub fn new() -> Self {
+        Connection {
+            view: ConnectionState::Connecting,
+            update: ConnectionState::Connecting,
+            unconfirmed: 0,
+        }
+    }
+
+    /// Set the state of one kind of call; true if it changed.
+    pub fn set(&mut self, kind: CallKind, state: ConnectionState) -> bool {
+        let current = match kind {
+            CallKind::View => &mut self.view,
+            CallKind::Update => &mut self.update,
+        };
+        if *current == state {
+            false
+        } else {
+            *current = state;
+            true
+        }
+    }
+
+    /// Window title, with the connection state unless both calls are connected, and the
+    /// number of unconfirmed events, if any.
+    pub fn title(&self, title: &str) -> String {
+        let title = match (&self.view, &self.update) {
+            (ConnectionState::Connected, ConnectionState::Connected) => title.to_string(),
+            (view, ConnectionState::Connected) => format!("{} [view: {}]", title, view.label()),
+            (ConnectionState::Connected, update) => {
+                format!("{} [update: {}]", title, update.label())
+            }
+            (view, update) => format!(
+                "{} [view: {}, update: {}]",
+                title,
+                view.label(),
+                update.label()
+            ),
+        };
+        match self.unconfirmed {
+            0 => title,
+            n => format!("{} [{} event(s) unconfirmed]", title, n),
+        }
+    }
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Connection::new()
+    }
+}
+
+/// Exponential backoff: delays double from `initial`, up to `max`, for at most
+/// `max_attempts` retries in a row.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    max_attempts: u32,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration, max_attempts: u32) -> Self {
+        Backoff {
+            initial,
+            max,
+            max_attempts,
+            attempt: 0,
+        }
+    }
+
+    /// Number of retries since the last success.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Delay before the next retry; `None` once there were `max_attempts` retries.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.attempt >= self.max_attempts {
+            return None;
+        }
+        let delay = self.initial * 2u32.pow(self.attempt.min(16));
+        self.attempt += 1;
+        Some(delay.min(self.max))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(Duration::from_millis(100), Duration::from_secs(10), 12)
+    }
+}
@@ -0,0 +1,113 @@
+//! In-process counter service: a port of `examples/counter/Counter.mo`.
+//!
+//! Keys `=`, `+`, `ArrowUp` and `ArrowRight` count up; `-`, `_`, `ArrowDown` and
+//! `ArrowLeft` count down (to zero). Like the example's `Terminal.Basic`, views apply
+//! their (pending) events to a copy of the count, and updates return no graphics.
+//!
+//! Events with nonces are done at most once: the counter remembers each user's last
+//! nonce, and skips the events up to it (e.g., in a retransmitted batch, or in a view of
+//! batches that it did already). It does not reorder events by nonce, so sessions make
+//! its update calls one at a time.
+
+use crate::error::IcmtResult;
+use crate::service::Service;
+use crate::types::{event, graphics, nat_ceil, Nat};
+
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+pub struct CounterService {
+    state: Mutex<Counter>,
+}
+
+/// The count, and the last nonce done for each user.
+#[derive(Debug, Clone, Default)]
+struct Counter {
+    count: u64,
+    nonces: BTreeMap<String, Nat>,
+}
+
+impl CounterService {
+    pub fn new() -> Self {
+        CounterService::default()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.state.lock().unwrap().count
+    }
+}
+
+impl Counter {
+    /// Do the event, unless it was done already (by its nonce).
+    fn apply(&mut self, event: &event::EventInfo) {
+        if let Some(nonce) = &event.nonce {
+            let user = &event.user_info.user_name;
+            if let Some(last) = self.nonces.get(user) {
+                if nonce <= last {
+                    return;
+                }
+            }
+            self.nonces.insert(user.clone(), nonce.clone());
+        }
+        update_count(&mut self.count, event);
+    }
+}
+
+fn update_count(count: &mut u64, event: &event::EventInfo) {
+    if let event::Event::KeyDown(keys) = &event.event {
+        for k in keys.iter() {
+            match k.key.as_str() {
+                "=" | "+" | "ArrowUp" | "ArrowRight" => *count += 1,
+                "-" | "_" | "ArrowDown" | "ArrowLeft" => *count = count.saturating_sub(1),
+                _ => { /* ignore key */ }
+            }
+        }
+    }
+}
+
+fn draw_count(count: u64, dim: &graphics::Dim) -> graphics::Elm {
+    let text = format!("count = {}", count);
+    let zoom = nat_ceil(&dim.width) / (text.len() as u32 * 6);
+    graphics::Elm::Text(graphics::TextElm {
+        pos: graphics::Pos::new(0, 0),
+        text,
+        color: graphics::color(255, 255, 255),
+        zoom: Nat::from(zoom.max(1)),
+    })
+}
+
+#[async_trait]
+impl Service for CounterService {
+    async fn view(
+        &self,
+        dim: graphics::Dim,
+        events: Vec<event::EventInfo>,
+    ) -> IcmtResult<graphics::Result> {
+        let mut counter = self.state.lock().unwrap().clone();
+        for ev in events.iter() {
+            counter.apply(ev);
+        }
+        Ok(graphics::Result::Ok(graphics::Out::Draw(draw_count(
+            counter.count,
+            &dim,
+        ))))
+    }
+
+    async fn update(
+        &self,
+        events: Vec<event::EventInfo>,
+        _req: graphics::Request,
+    ) -> IcmtResult<Vec<graphics::Result>> {
+        let mut counter = self.state.lock().unwrap();
+        for ev in events.iter() {
+            counter.apply(ev);
+        }
+        Ok(vec![])
+    }
+
+    fn dedupes_nonces(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,216 @@
+//! dfx projects: network URLs and canister IDs, by name.
+//!
+//! From inside a dfx project (or any directory below one), `ic-mt connect counter`
+//! finds the project's `dfx.json`, and looks up the canister ID in
+//! `.dfx/<network>/canister_ids.json` (for the local network) or `canister_ids.json`.
+
+use crate::error::{Context, IcmtError, IcmtResult};
+use crate::network::{self, Network};
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// A dfx project, from its `dfx.json` file.
+#[derive(Debug, Clone)]
+pub struct DfxProject {
+    pub root: PathBuf,
+    json: Value,
+}
+
+fn read_json(path: &Path) -> IcmtResult<Value> {
+    let text = std::fs::read_to_string(path).context(|| format!("cannot read {:?}", path))?;
+    serde_json::from_str(&text)
+        .map_err(|e| IcmtError::String(format!("failed to parse {:?}: {}", path, e)))
+}
+
+impl DfxProject {
+    /// Find the project that contains the current directory, if any.
+    pub fn find() -> IcmtResult<Option<DfxProject>> {
+        let cwd = std::env::current_dir()?;
+        for dir in cwd.ancestors() {
+            if dir.join("dfx.json").exists() {
+                return Ok(Some(DfxProject::open(dir)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Open the project at a root directory (with its `dfx.json`).
+    pub fn open(root: &Path) -> IcmtResult<DfxProject> {
+        Ok(DfxProject {
+            root: root.to_path_buf(),
+            json: read_json(&root.join("dfx.json"))?,
+        })
+    }
+
+    /// URL of the local replica, from the project's network config.
+    pub fn local_url(&self) -> Option<String> {
+        if let Some(bind) = self.json["networks"]["local"]["bind"].as_str() {
+            return Some(format!("http://{}", bind));
+        }
+        let start = &self.json["defaults"]["start"];
+        match (start["address"].as_str(), start["port"].as_u64()) {
+            (Some(address), Some(port)) => Some(format!("http://{}:{}", address, port)),
+            (None, Some(port)) => Some(format!("http://127.0.0.1:{}", port)),
+            _ => None,
+        }
+    }
+
+    /// Names of the networks in the project's network config.
+    pub fn network_names(&self) -> Vec<String> {
+        match self.json["networks"].as_object() {
+            Some(networks) => networks.keys().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    /// URL of a (non-local) named network, from the project's network config.
+    pub fn network_url(&self, name: &str) -> Option<String> {
+        let network = &self.json["networks"][name];
+        match network["providers"][0].as_str() {
+            Some(url) => Some(url.to_string()),
+            None => network["bind"]
+                .as_str()
+                .map(|bind| format!("http://{}", bind)),
+        }
+    }
+
+    /// Name of the network that the project serves at a URL: a network whose providers
+    /// (or bind address) give the URL, or the local one.
+    pub fn network_name_for_url(&self, url: &str) -> Option<String> {
+        let url = url.trim_end_matches('/');
+        if let Some(networks) = self.json["networks"].as_object() {
+            for (name, network) in networks.iter() {
+                let providers = network["providers"].as_array().into_iter().flatten();
+                let mut urls: Vec<String> = providers
+                    .filter_map(|p| p.as_str())
+                    .map(|p| p.to_string())
+                    .collect();
+                if let Some(bind) = network["bind"].as_str() {
+                    urls.push(format!("http://{}", bind));
+                }
+                if urls.iter().any(|u| u.trim_end_matches('/') == url) {
+                    return Some(name.clone());
+                }
+            }
+        }
+        let local_url = self
+            .local_url()
+            .unwrap_or_else(|| network::LOCAL_URL.to_string());
+        if local_url == url {
+            return Some("local".to_string());
+        }
+        None
+    }
+
+    /// Canister ID of the named canister, on the named network.
+    pub fn canister_id(&self, network_name: &str, canister_name: &str) -> IcmtResult<String> {
+        let mut paths = vec![self
+            .root
+            .join(".dfx")
+            .join(network_name)
+            .join("canister_ids.json")];
+        if network_name != "local" {
+            paths.insert(0, self.root.join("canister_ids.json"));
+        }
+        for path in paths.iter() {
+            if !path.exists() {
+                continue;
+            }
+            let ids = read_json(path)?;
+            if let Some(ids) = ids.as_object() {
+                let entry = ids.get(canister_name).or_else(|| {
+                    ids.iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case(canister_name))
+                        .map(|(_, entry)| entry)
+                });
+                if let Some(id) = entry.and_then(|e| e[network_name].as_str()) {
+                    return Ok(id.to_string());
+                }
+            }
+        }
+        Err(IcmtError::String(format!(
+            "no canister named {:?} on network {:?} (looked in {:?}); is it deployed?",
+            canister_name, network_name, paths
+        )))
+    }
+}
+
+/// Replica URL, network (for root key trust) and canister ID, for a network and a
+/// canister (an ID, or a name in the dfx project of the current directory).
+pub fn resolve(network: &Network, canister: &str) -> IcmtResult<(String, Network, String)> {
+    resolve_in(DfxProject::find()?.as_ref(), network, canister)
+}
+
+/// Resolve, as [`resolve`], with names from the given project (if any).
+///
+/// A canister name on a replica URL is only looked up when the project serves a network
+/// at that URL; the canister IDs of another network (the IC's) would be the wrong ones.
+pub fn resolve_in(
+    project: Option<&DfxProject>,
+    network: &Network,
+    canister: &str,
+) -> IcmtResult<(String, Network, String)> {
+    let (network_name, url) = match network {
+        Network::Local => (
+            Some("local".to_string()),
+            project
+                .and_then(|p| p.local_url())
+                .unwrap_or_else(|| network::LOCAL_URL.to_string()),
+        ),
+        Network::Ic => (Some("ic".to_string()), network::IC_URL.to_string()),
+        Network::Named(name) => match project.and_then(|p| p.network_url(name)) {
+            Some(provider) => (Some(name.clone()), provider),
+            None => return Err(unknown_network(name, project)),
+        },
+        Network::Url(url) => (
+            project.and_then(|p| p.network_name_for_url(url)),
+            url.clone(),
+        ),
+    };
+    let network = match network {
+        Network::Named(_) => Network::Url(url.clone()),
+        network => network.clone(),
+    };
+    let canister_id = if ic_types::Principal::from_text(canister).is_ok() {
+        canister.to_string()
+    } else {
+        match (project, network_name) {
+            (Some(project), Some(network_name)) => project.canister_id(&network_name, canister)?,
+            (Some(_), None) => {
+                return Err(IcmtError::String(format!(
+                    "{:?} is not a canister ID, and no network in dfx.json is at {}; \
+                     give the canister ID",
+                    canister, url
+                )))
+            }
+            (None, _) => {
+                return Err(IcmtError::String(format!(
+                    "{:?} is not a canister ID, and there is no dfx.json to name it",
+                    canister
+                )))
+            }
+        }
+    };
+    Ok((url, network, canister_id))
+}
+
+/// Error for a network name that is neither built in nor in the project's `dfx.json`.
+fn unknown_network(name: &str, project: Option<&DfxProject>) -> IcmtError {
+    let mut known = vec!["local".to_string(), "ic".to_string()];
+    match project {
+        Some(project) => {
+            for name in project.network_names() {
+                if !known.contains(&name) {
+                    known.push(name);
+                }
+            }
+        }
+        None => known.push("(no dfx.json to name others)".to_string()),
+    }
+    IcmtError::String(format!(
+        "unknown network {:?}; known networks: {}",
+        name,
+        known.join(", ")
+    ))
+}
@@ -1,24 +1,29 @@
 //! Draw.
 
-use log::{error, trace, warn};
+use log::{debug, error, trace};
 
 use crate::{
     color::*,
     types::{
         graphics::{self, Elm, Fill},
-        nat_ceil,
+        nat_ceil, nat_i32,
     },
 };
 
 use candid::Nat;
+use icmt_core::check::{bounding_rect, check_bitmap};
+use icmt_core::glyph::{glyph_cell, layout, GLYPH_PAD, GLYPH_SIZE};
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{Canvas, RenderTarget};
+use sdl2::surface::Surface;
+use sdl2::video::Window;
+use std::convert::TryFrom;
 
 fn translate_rect(pos: &graphics::Pos, r: &graphics::Rect) -> sdl2::rect::Rect {
-    // todo -- clip the size of the rect dimension by the bound param
     trace!("translate_rect {:?} {:?}", pos, r);
     sdl2::rect::Rect::new(
-        nat_ceil(&Nat(&pos.x.0 + &r.pos.x.0)) as i32,
-        nat_ceil(&Nat(&pos.y.0 + &r.pos.y.0)) as i32,
+        nat_i32(&Nat(&pos.x.0 + &r.pos.x.0)),
+        nat_i32(&Nat(&pos.y.0 + &r.pos.y.0)),
         nat_ceil(&r.dim.width),
         nat_ceil(&r.dim.height),
     )
@@ -40,29 +45,189 @@ fn draw_rect<T: RenderTarget>(
             canvas.set_draw_color(c);
             canvas.fill_rect(r).unwrap();
         }
-        Fill::Open(c, _) => {
+        Fill::Open(c, width) => {
             let r = translate_rect(pos, r);
             let c = translate_color(c);
             canvas.set_draw_color(c);
-            canvas.draw_rect(r).unwrap();
+            canvas.fill_rects(&border_rects(&r, nat_ceil(width))).unwrap();
         }
     }
 }
 
+/// Rects of a border with the given width, inset into the rect.
+fn border_rects(r: &sdl2::rect::Rect, width: u32) -> Vec<sdl2::rect::Rect> {
+    use sdl2::rect::Rect;
+    let (w, h) = (r.width(), r.height());
+    if width == 0 {
+        vec![]
+    } else if width.saturating_mul(2) >= w || width.saturating_mul(2) >= h {
+        // the border covers the interior.
+        vec![*r]
+    } else {
+        let inner_h = h - width * 2;
+        let (x, y, b) = (r.x(), r.y(), width as i32);
+        vec![
+            Rect::new(x, y, w, width),
+            Rect::new(x, y + h as i32 - b, w, width),
+            Rect::new(x, y + b, width, inner_h),
+            Rect::new(x + w as i32 - b, y + b, width, inner_h),
+        ]
+    }
+}
+
+/// Rect of the glyph cell at column `i` and row `j` of the glyph at column `col` and line
+/// `line`, if it is within the native integer range.
+fn glyph_cell_rect(
+    (x0, y0): (i32, i32),
+    zoom: u32,
+    (col, line): (u32, u32),
+    (i, j): (u32, u32),
+) -> Option<sdl2::rect::Rect> {
+    let advance = (GLYPH_SIZE + GLYPH_PAD).checked_mul(zoom)?;
+    let offset = |glyph: u32, cell: u32| -> Option<i32> {
+        let n = glyph
+            .checked_mul(advance)?
+            .checked_add(cell.checked_mul(zoom)?)?;
+        i32::try_from(n).ok()
+    };
+    Some(sdl2::rect::Rect::new(
+        x0.checked_add(offset(col, i)?)?,
+        y0.checked_add(offset(line, j)?)?,
+        zoom,
+        zoom,
+    ))
+}
+
+fn draw_text<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    pos: &graphics::Pos,
+    t: &graphics::TextElm,
+) -> Result<(), String> {
+    let zoom = nat_ceil(&t.zoom);
+    if zoom == 0 {
+        return Ok(());
+    }
+    let origin = (
+        nat_i32(&Nat(&pos.x.0 + &t.pos.x.0)),
+        nat_i32(&Nat(&pos.y.0 + &t.pos.y.0)),
+    );
+    let mut cells = vec![];
+    for (col, line, bits) in layout(&t.text) {
+        let bits = match bits {
+            Some(bits) => bits,
+            None => {
+                trace!("draw_text: no glyph at column {} of line {}", col, line);
+                continue;
+            }
+        };
+        for j in 0..GLYPH_SIZE {
+            for i in 0..GLYPH_SIZE {
+                if glyph_cell(&bits, i, j) {
+                    match glyph_cell_rect(origin, zoom, (col, line), (i, j)) {
+                        Some(r) => cells.push(r),
+                        None => {
+                            error!("Ignoring text that exceeds the native integer range");
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    canvas.set_draw_color(translate_color(&t.color));
+    canvas.fill_rects(&cells)
+}
+
+/// Expand the bitmap's pixels to RGB24, for an SDL texture.
+fn bitmap_rgb24(b: &graphics::Bitmap) -> Vec<u8> {
+    match &b.palette {
+        None => b.pixels.clone(),
+        Some(palette) => {
+            let palette: Vec<sdl2::pixels::Color> = palette.iter().map(translate_color).collect();
+            let mut rgb = Vec::with_capacity(b.pixels.len() * 3);
+            for i in b.pixels.iter() {
+                let c = palette[*i as usize];
+                rgb.extend_from_slice(&[c.r, c.g, c.b]);
+            }
+            rgb
+        }
+    }
+}
+
+/// A render target whose canvas can create textures; SDL only offers a texture creator
+/// per concrete target, so bitmaps need this beyond `RenderTarget`.
+pub trait TextureTarget: RenderTarget + Sized {
+    /// Copy RGB24 pixels of the given size onto the canvas, scaled to `dst`.
+    fn blit_rgb24(
+        canvas: &mut Canvas<Self>,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        dst: sdl2::rect::Rect,
+    ) -> Result<(), String>;
+}
+
+macro_rules! impl_texture_target {
+    ($($target:ty),*) => {$(
+        impl TextureTarget for $target {
+            fn blit_rgb24(
+                canvas: &mut Canvas<Self>,
+                width: u32,
+                height: u32,
+                pixels: &[u8],
+                dst: sdl2::rect::Rect,
+            ) -> Result<(), String> {
+                let texture_creator = canvas.texture_creator();
+                let mut texture = texture_creator
+                    .create_texture_static(PixelFormatEnum::RGB24, width, height)
+                    .map_err(|e| e.to_string())?;
+                texture
+                    .update(None, pixels, width as usize * 3)
+                    .map_err(|e| e.to_string())?;
+                canvas.copy(&texture, None, Some(dst))
+            }
+        }
+    )*};
+}
+
+impl_texture_target!(Window, Surface<'_>);
+
+fn draw_bitmap<T: TextureTarget>(
+    canvas: &mut Canvas<T>,
+    pos: &graphics::Pos,
+    b: &graphics::Bitmap,
+) -> Result<(), String> {
+    if let Err(e) = check_bitmap(b) {
+        error!("Ignoring invalid bitmap: {}", e);
+        return Ok(());
+    }
+    let (width, height) = (nat_ceil(&b.width), nat_ceil(&b.height));
+    if width == 0 || height == 0 {
+        return Ok(());
+    }
+    T::blit_rgb24(
+        canvas,
+        width,
+        height,
+        &bitmap_rgb24(b),
+        translate_rect(pos, &b.rect),
+    )
+}
+
 pub fn nat_zero() -> Nat {
     Nat::from(0)
 }
 
-pub fn draw_rect_elms<T: RenderTarget>(
+pub fn draw_rect_elms<'a, T: TextureTarget>(
     canvas: &mut Canvas<T>,
     pos: &graphics::Pos,
     dim: &graphics::Dim,
     fill: &graphics::Fill,
-    elms: &graphics::Elms,
+    elms: impl IntoIterator<Item = &'a graphics::Elm>,
 ) -> Result<(), String> {
     draw_rect::<T>(
         canvas,
-        &pos,
+        pos,
         &graphics::Rect::new(
             nat_zero(),
             nat_zero(),
@@ -71,58 +236,201 @@ pub fn draw_rect_elms<T: RenderTarget>(
         ),
         fill,
     );
-    for elm in elms.iter() {
+    for elm in elms {
         draw_elm(canvas, pos, elm)?
     }
     Ok(())
 }
 
-pub fn draw_elm<T: RenderTarget>(
+pub fn draw_elm<T: TextureTarget>(
     canvas: &mut Canvas<T>,
     pos: &graphics::Pos,
     elm: &graphics::Elm,
 ) -> Result<(), String> {
-    match &elm {
-        &Elm::Node(node) => {
+    match elm {
+        Elm::Node(node) => {
             let pos = graphics::Pos {
                 x: Nat(&pos.x.0 + &node.rect.pos.x.0),
                 y: Nat(&pos.y.0 + &node.rect.pos.y.0),
             };
-            draw_rect_elms(canvas, &pos, &node.rect.dim, &node.fill, &node.elms)
+            // clip the node's subtree to the node (and to any enclosing clip).
+            let clip0 = canvas.clip_rect();
+            let (w, h) = (nat_ceil(&node.rect.dim.width), nat_ceil(&node.rect.dim.height));
+            let r = sdl2::rect::Rect::new(nat_i32(&pos.x), nat_i32(&pos.y), w, h);
+            let clip = match clip0 {
+                _ if w == 0 || h == 0 => None,
+                None => Some(r),
+                Some(c) => c.intersection(r),
+            };
+            let clip = match clip {
+                None => {
+                    trace!("draw_elm: node is clipped out entirely");
+                    return Ok(());
+                }
+                Some(clip) => clip,
+            };
+            canvas.set_clip_rect(clip);
+            let res = draw_rect_elms(canvas, &pos, &node.rect.dim, &node.fill, &node.elms);
+            canvas.set_clip_rect(clip0);
+            res
         }
-        &Elm::Rect(r, f) => {
+        Elm::Rect(r, f) => {
             draw_rect(canvas, pos, r, f);
             Ok(())
         }
+        Elm::Text(t) => draw_text(canvas, pos, t),
+        Elm::Bitmap(b) => draw_bitmap(canvas, pos, b),
+    }
+}
+
+/// Region of the screen that differs from what was last drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    Clean,
+    Region(sdl2::rect::Rect),
+    Full,
+}
+
+impl Damage {
+    fn add(self, r: sdl2::rect::Rect) -> Damage {
+        match self {
+            Damage::Clean => Damage::Region(r),
+            Damage::Region(r0) => Damage::Region(r0.union(r)),
+            Damage::Full => Damage::Full,
+        }
     }
 }
 
-pub async fn draw<T: RenderTarget>(
+/// Persistent table of named layers, replaced piecemeal by `Out::Redraw`.
+///
+/// Layers composite in the order that their names first appear.
+/// An `Out::Draw` replaces the entire table with its one element.
+#[derive(Debug, Clone)]
+pub struct Layers {
+    layers: Vec<(String, Elm)>,
+    damage: Damage,
+}
+
+impl Layers {
+    pub fn new() -> Layers {
+        Layers {
+            layers: vec![],
+            damage: Damage::Full,
+        }
+    }
+
+    /// Region that changed since the last call to `clear_damage`.
+    pub fn damage(&self) -> Damage {
+        self.damage
+    }
+
+    pub fn clear_damage(&mut self) {
+        self.damage = Damage::Clean
+    }
+
+    /// Mark the whole screen as changed (e.g., after its size changes).
+    pub fn invalidate(&mut self) {
+        self.damage = Damage::Full
+    }
+
+    fn damage_elm(&mut self, elm: &Elm) {
+        let origin = graphics::Pos {
+            x: nat_zero(),
+            y: nat_zero(),
+        };
+        self.damage = self
+            .damage
+            .add(translate_rect(&origin, &bounding_rect(elm)));
+    }
+
+    /// Replace one named layer, or add it (on top) if it is new.
+    pub fn set(&mut self, name: String, elm: Elm) {
+        match self.layers.iter().position(|(n, _)| n == &name) {
+            Some(i) => {
+                if self.layers[i].1 == elm {
+                    trace!("layer {:?} unchanged", name);
+                    return;
+                }
+                self.damage_elm(&elm);
+                let old = std::mem::replace(&mut self.layers[i].1, elm);
+                self.damage_elm(&old);
+            }
+            None => {
+                self.damage_elm(&elm);
+                self.layers.push((name, elm))
+            }
+        }
+    }
+
+    /// Apply the service's graphics result to the table.
+    pub fn apply(&mut self, rr: graphics::Result) {
+        match rr {
+            graphics::Result::Ok(graphics::Out::Draw(elm)) => {
+                self.layers = vec![("screen".to_string(), elm)];
+                self.damage = Damage::Full;
+            }
+            graphics::Result::Ok(graphics::Out::Redraw(elms)) => {
+                debug!("redraw {} named layer(s)", elms.len());
+                for (name, elm) in elms.into_iter() {
+                    self.set(name, elm)
+                }
+            }
+            graphics::Result::Ok(graphics::Out::Commands(cmds)) => {
+                trace!("ignoring commands (not graphics): {:?}", cmds);
+            }
+            graphics::Result::Err(opt_message) => match opt_message {
+                None => error!("Error result from server. No message."),
+                Some(ref m) => error!("Error message from server: {}", m),
+            },
+        }
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers::new()
+    }
+}
+
+/// Software canvas of the given size, e.g., to keep the last composite between frames.
+pub fn create_surface_canvas(dim: &graphics::Dim) -> Result<Canvas<Surface<'static>>, String> {
+    let surface = Surface::new(
+        nat_ceil(&dim.width),
+        nat_ceil(&dim.height),
+        PixelFormatEnum::RGBA8888,
+    )?;
+    surface.into_canvas()
+}
+
+/// Composite the layers onto the canvas.
+///
+/// With `damage_only`, only the damaged region is cleared and redrawn; this assumes the
+/// canvas still holds the last composite, which holds for a surface, but not for a window,
+/// whose back buffer SDL invalidates at each present (see `WindowSink`).
+pub async fn draw<T: TextureTarget>(
     canvas: &mut Canvas<T>,
     dim: &graphics::Dim,
-    rr: &graphics::Result,
+    layers: &Layers,
+    damage_only: bool,
 ) -> Result<(), String> {
     let pos = graphics::Pos {
         x: nat_zero(),
         y: nat_zero(),
     };
     let fill = graphics::Fill::Closed((nat_zero(), nat_zero(), nat_zero()));
-    match rr {
-        graphics::Result::Ok(graphics::Out::Draw(elm)) => {
-            draw_rect_elms(canvas, &pos, dim, &fill, &vec![elm.clone()])?;
-        }
-        graphics::Result::Ok(graphics::Out::Redraw(elms)) => {
-            if elms.len() == 1 && elms[0].0 == "screen" {
-                draw_rect_elms(canvas, &pos, dim, &fill, &vec![elms[0].1.clone()])?;
-            } else {
-                warn!("unrecognized redraw elements {:?}", elms);
-            }
+    let clip = match (damage_only, layers.damage()) {
+        (true, Damage::Clean) => {
+            canvas.present();
+            return Ok(());
         }
-        graphics::Result::Err(opt_message) => match opt_message {
-            None => error!("Error result from server. No message."),
-            Some(ref m) => error!("Error message from server: {}", m),
-        },
+        (true, Damage::Region(r)) => Some(r),
+        (true, Damage::Full) | (false, _) => None,
     };
+    canvas.set_clip_rect(clip);
+    let elms = layers.layers.iter().map(|(_, elm)| elm);
+    let res = draw_rect_elms(canvas, &pos, dim, &fill, elms);
+    canvas.set_clip_rect(None);
+    res?;
     canvas.present();
     // to do -- if enabled, dump canvas as .BMP file to next output image file in the stream that we are producing
     // https://docs.rs/sdl2/0.34.3/sdl2/render/struct.Canvas.html#method.into_surface
@@ -1,21 +1,148 @@
 //! Errors generated from the mini terminal.
 
-use log::error;
+use log::debug;
+use std::fmt;
 
 /// Result from mini terminal.
 pub type IcmtResult<X> = Result<X, IcmtError>;
 
+/// Process exit codes, by what failed (for scripting).
+pub mod exit_code {
+    /// Other errors (e.g., invalid configuration).
+    pub const OTHER: i32 = 1;
+    /// Reading or writing files.
+    pub const IO: i32 = 3;
+    /// Calling the service, via the IC agent.
+    pub const AGENT: i32 = 4;
+    /// Encoding or decoding Candid messages.
+    pub const CANDID: i32 = 5;
+    /// Loading or generating identity keys.
+    pub const IDENTITY: i32 = 6;
+    /// Writing captures.
+    pub const CAPTURE: i32 = 7;
+}
+
 /// Errors from the mini terminal, or its subcomponents.
 #[derive(Debug)]
 pub enum IcmtError {
     Candid(std::sync::Arc<candid::Error>),
-    Agent(), /* Clone => Agent(ic_agent::AgentError) */
+    Agent(std::sync::Arc<ic_agent::AgentError>),
     String(String),
-    Engiffen(), /* Clone => engiffen::Error */
+    Engiffen(std::sync::Arc<engiffen::Error>),
+    Io(std::sync::Arc<std::io::Error>),
     RingKeyRejected(ring::error::KeyRejected),
     RingUnspecified(ring::error::Unspecified),
     FromHexError(hex::FromHexError),
     PemError(pem::PemError),
+    /// Error with context: what was being done (e.g., reading a file, calling a method).
+    Context {
+        context: String,
+        source: Box<IcmtError>,
+    },
+    /// Error with the exit code for what failed (e.g., loading an identity).
+    Exit {
+        code: i32,
+        source: Box<IcmtError>,
+    },
+}
+
+impl IcmtError {
+    /// Transient errors (from the agent's transport, or timeouts) may not recur when
+    /// retried; rejects, traps and undecodable replies would.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            IcmtError::Agent(e) => is_transient_agent_error(e),
+            IcmtError::Context { source, .. } | IcmtError::Exit { source, .. } => {
+                source.is_transient()
+            }
+            _ => false,
+        }
+    }
+
+    /// Process exit code for this error: the one set where it happened (see `ExitCode`);
+    /// without one, only agent and engiffen errors say what failed.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            IcmtError::Exit { code, .. } => *code,
+            IcmtError::Context { source, .. } => source.exit_code(),
+            IcmtError::Agent(_) => exit_code::AGENT,
+            IcmtError::Engiffen(_) => exit_code::CAPTURE,
+            _ => exit_code::OTHER,
+        }
+    }
+}
+
+/// Is the agent's error from the transport, or a timeout (rather than from the canister)?
+fn is_transient_agent_error(e: &ic_agent::AgentError) -> bool {
+    use ic_agent::AgentError;
+    match e {
+        AgentError::TransportError(_) | AgentError::TimeoutWaitingForResponse() => true,
+        // e.g., the replica is starting, or overloaded.
+        AgentError::HttpError(payload) => payload.status >= 500 || payload.status == 429,
+        // SYS_TRANSIENT (e.g., a full queue); other reject codes are for good.
+        AgentError::ReplicaError { reject_code, .. } => *reject_code == 2,
+        _ => false,
+    }
+}
+
+impl fmt::Display for IcmtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IcmtError::Context { context, .. } => write!(f, "{}", context),
+            IcmtError::Exit { source, .. } => write!(f, "{}", source),
+            IcmtError::Candid(_) => write!(f, "invalid Candid message"),
+            IcmtError::Agent(_) => write!(f, "service call failed"),
+            IcmtError::String(s) => write!(f, "{}", s),
+            IcmtError::Engiffen(e) => write!(f, "cannot make GIF capture: {:?}", e),
+            IcmtError::Io(_) => write!(f, "input/output error"),
+            IcmtError::RingKeyRejected(e) => write!(f, "key rejected: {}", e),
+            IcmtError::RingUnspecified(_) => write!(f, "cryptography error"),
+            IcmtError::FromHexError(_) => write!(f, "invalid hex text"),
+            IcmtError::PemError(e) => write!(f, "invalid PEM file: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for IcmtError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IcmtError::Context { source, .. } => Some(source.as_ref()),
+            IcmtError::Exit { source, .. } => source.source(),
+            IcmtError::Candid(e) => Some(e.as_ref()),
+            IcmtError::Agent(e) => Some(e.as_ref()),
+            IcmtError::Io(e) => Some(e.as_ref()),
+            IcmtError::FromHexError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Add context to errors: what was being done when they happened.
+pub trait Context<X> {
+    fn context<C: Into<String>>(self, context: impl FnOnce() -> C) -> IcmtResult<X>;
+}
+
+impl<X, E: Into<IcmtError>> Context<X> for Result<X, E> {
+    fn context<C: Into<String>>(self, context: impl FnOnce() -> C) -> IcmtResult<X> {
+        self.map_err(|e| IcmtError::Context {
+            context: context().into(),
+            source: Box::new(e.into()),
+        })
+    }
+}
+
+/// Set the exit code for errors, by what failed (see `exit_code`).
+pub trait ExitCode<X> {
+    fn with_exit_code(self, code: i32) -> IcmtResult<X>;
+}
+
+impl<X, E: Into<IcmtError>> ExitCode<X> for Result<X, E> {
+    fn with_exit_code(self, code: i32) -> IcmtResult<X> {
+        self.map_err(|e| IcmtError::Exit {
+            code,
+            source: Box::new(e.into()),
+        })
+    }
 }
 
 impl std::convert::From<pem::PemError> for IcmtError {
@@ -32,9 +159,8 @@ impl std::convert::From<hex::FromHexError> for IcmtError {
 
 impl std::convert::From<ic_agent::AgentError> for IcmtError {
     fn from(ae: ic_agent::AgentError) -> Self {
-        error!("Detected agent error: {:?}", ae);
-        /*IcmtError::Agent(ae)*/
-        IcmtError::Agent()
+        debug!("Detected agent error: {:?}", ae);
+        IcmtError::Agent(std::sync::Arc::new(ae))
     }
 }
 
@@ -44,19 +170,14 @@ impl std::convert::From<candid::Error> for IcmtError {
     }
 }
 
-impl<T> std::convert::From<std::sync::mpsc::SendError<T>> for IcmtError {
-    fn from(_s: std::sync::mpsc::SendError<T>) -> Self {
+impl<T> std::convert::From<tokio::sync::mpsc::error::SendError<T>> for IcmtError {
+    fn from(_s: tokio::sync::mpsc::error::SendError<T>) -> Self {
         IcmtError::String("send error".to_string())
     }
 }
-impl std::convert::From<std::sync::mpsc::RecvError> for IcmtError {
-    fn from(_s: std::sync::mpsc::RecvError) -> Self {
-        IcmtError::String("recv error".to_string())
-    }
-}
 impl std::convert::From<std::io::Error> for IcmtError {
-    fn from(_s: std::io::Error) -> Self {
-        IcmtError::String("IO error".to_string())
+    fn from(e: std::io::Error) -> Self {
+        IcmtError::Io(std::sync::Arc::new(e))
     }
 }
 impl std::convert::From<String> for IcmtError {
@@ -75,7 +196,7 @@ impl std::convert::From<ring::error::Unspecified> for IcmtError {
     }
 }
 impl std::convert::From<engiffen::Error> for IcmtError {
-    fn from(_e: engiffen::Error) -> Self {
-        IcmtError::Engiffen()
+    fn from(e: engiffen::Error) -> Self {
+        IcmtError::Engiffen(std::sync::Arc::new(e))
     }
 }
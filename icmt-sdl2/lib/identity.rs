@@ -0,0 +1,207 @@
+//! Identities (key pairs) for signing calls, kept in the per-user configuration directory.
+//!
+//! Each identity is a PEM file, at `<config dir>/identity/<name>/identity.pem`.
+//! One of them is selected (`identity use`); on first run, the "default" identity
+//! is generated and selected, so that the user's principal is stable across sessions.
+//!
+//! Keys are Ed25519 (PKCS#8, as generated here) or secp256k1 (SEC1, as generated by
+//! `dfx identity new`); dfx's identities are found by name (`dfx_pem_path`).
+
+use crate::config::config_dir;
+use crate::error::{exit_code, Context, ExitCode, IcmtError, IcmtResult};
+
+use ic_agent::identity::{BasicIdentity, Secp256k1Identity};
+use ic_agent::{Identity, Signature};
+use ic_types::Principal;
+use log::info;
+use ring::signature::Ed25519KeyPair;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_IDENTITY: &str = "default";
+
+pub fn identities_dir() -> PathBuf {
+    config_dir().join("identity")
+}
+
+pub fn pem_path(name: &str) -> PathBuf {
+    identities_dir().join(name).join("identity.pem")
+}
+
+/// PEM file of a dfx identity (`dfx identity new <name>`).
+pub fn dfx_pem_path(name: &str) -> PathBuf {
+    PathBuf::from(shellexpand::tilde("~/.config/dfx/identity").into_owned())
+        .join(name)
+        .join("identity.pem")
+}
+
+/// Check that an identity name names a directory in the identities directory (e.g., that
+/// it has no path separators).
+pub fn check_name(name: &str) -> IcmtResult<()> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        Err(IcmtError::String(format!("invalid identity name {:?}", name)))
+    } else {
+        Ok(())
+    }
+}
+
+fn selected_path() -> PathBuf {
+    identities_dir().join("selected")
+}
+
+/// Name of the selected identity.
+pub fn selected() -> IcmtResult<String> {
+    let path = selected_path();
+    if path.exists() {
+        let name = std::fs::read_to_string(&path)
+            .context(|| format!("cannot read selected identity from {:?}", path))?;
+        Ok(name.trim().to_string())
+    } else {
+        Ok(DEFAULT_IDENTITY.to_string())
+    }
+}
+
+/// Select an (existing) identity for later sessions.
+pub fn select(name: &str) -> IcmtResult<()> {
+    check_name(name)?;
+    if !pem_path(name).exists() {
+        return Err(IcmtError::String(format!("no identity named {:?}", name)));
+    }
+    let path = selected_path();
+    std::fs::create_dir_all(identities_dir())
+        .and_then(|()| std::fs::write(&path, name))
+        .context(|| format!("cannot write selected identity to {:?}", path))?;
+    Ok(())
+}
+
+/// Names of all identities.
+pub fn list() -> IcmtResult<Vec<String>> {
+    let dir = identities_dir();
+    let mut names = vec![];
+    if dir.exists() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.path().join("identity.pem").exists() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Generate a new (Ed25519) identity, and write its PEM file.
+pub fn create(name: &str) -> IcmtResult<PathBuf> {
+    generate(name).with_exit_code(exit_code::IDENTITY)
+}
+
+fn generate(name: &str) -> IcmtResult<PathBuf> {
+    check_name(name)?;
+    let path = pem_path(name);
+    if path.exists() {
+        return Err(IcmtError::String(format!("identity {:?} already exists", name)));
+    }
+    let rng = ring::rand::SystemRandom::new();
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)?;
+    let pem = pem::encode(&pem::Pem {
+        tag: "PRIVATE KEY".to_string(),
+        contents: pkcs8.as_ref().to_vec(),
+    });
+    std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|()| write_private(&path, pem.as_bytes()))
+        .context(|| format!("cannot write identity file {:?}", path))?;
+    info!("Created identity {:?} at {:?}", name, path);
+    Ok(path)
+}
+
+/// Write a new file that only its owner can read (from the start, not after a chmod).
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)
+}
+
+/// PEM file of the selected identity, generating the default identity on first run.
+pub fn selected_pem_path() -> IcmtResult<PathBuf> {
+    let name = selected()?;
+    check_name(&name)?;
+    let path = pem_path(&name);
+    if !path.exists() {
+        if name == DEFAULT_IDENTITY {
+            info!("Creating identity {:?} (first run)...", name);
+            create(&name)?;
+        } else {
+            return Err(IcmtError::String(format!(
+                "selected identity {:?} has no file {:?}",
+                name, path
+            )))
+            .with_exit_code(exit_code::IDENTITY);
+        }
+    }
+    Ok(path)
+}
+
+/// Identity that signs calls, with either kind of key.
+pub enum KeyIdentity {
+    Ed25519(BasicIdentity),
+    Secp256k1(Secp256k1Identity),
+}
+
+impl Identity for KeyIdentity {
+    fn sender(&self) -> Result<Principal, String> {
+        match self {
+            KeyIdentity::Ed25519(i) => i.sender(),
+            KeyIdentity::Secp256k1(i) => i.sender(),
+        }
+    }
+    fn sign(&self, blob: &[u8]) -> Result<Signature, String> {
+        match self {
+            KeyIdentity::Ed25519(i) => i.sign(blob),
+            KeyIdentity::Secp256k1(i) => i.sign(blob),
+        }
+    }
+}
+
+/// Load an identity from its PEM file.
+///
+/// `PRIVATE KEY` is an Ed25519 key (PKCS#8); `EC PRIVATE KEY` is a secp256k1 key (SEC1).
+pub fn load(pem_path: &Path) -> IcmtResult<KeyIdentity> {
+    load_key(pem_path).with_exit_code(exit_code::IDENTITY)
+}
+
+fn load_key(pem_path: &Path) -> IcmtResult<KeyIdentity> {
+    let bytes =
+        std::fs::read(pem_path).context(|| format!("cannot read pem file {:?}", pem_path))?;
+    info!("Parsing pem file {:?}", pem_path);
+    for pem in pem::parse_many(&bytes) {
+        match pem.tag.as_str() {
+            "PRIVATE KEY" => {
+                let key_pair = Ed25519KeyPair::from_pkcs8(&pem.contents)?;
+                return Ok(KeyIdentity::Ed25519(BasicIdentity::from_key_pair(key_pair)));
+            }
+            "EC PRIVATE KEY" => {
+                let identity = Secp256k1Identity::from_pem(bytes.as_slice()).map_err(|e| {
+                    IcmtError::String(format!("invalid secp256k1 key in {:?}: {}", pem_path, e))
+                })?;
+                return Ok(KeyIdentity::Secp256k1(identity));
+            }
+            // e.g., "EC PARAMETERS", before dfx's secp256k1 keys.
+            _ => continue,
+        }
+    }
+    Err(IcmtError::String(format!(
+        "no supported private key in pem file {:?}",
+        pem_path
+    )))
+}
+
+/// Principal of an identity.
+pub fn principal(identity: &KeyIdentity) -> IcmtResult<Principal> {
+    Ok(identity.sender()?)
+}
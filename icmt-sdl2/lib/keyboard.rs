@@ -3,17 +3,264 @@
 use sdl2::keyboard::Keycode;
 use sdl2::keyboard::Mod;
 
-use super::types::event::KeyEventInfo;
+use super::error::{Context, IcmtError, IcmtResult};
+use super::types::event::{Event, KeyEventInfo};
 
 use log::info;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// A key (by its SDL key name, e.g., "F5" or "Q", in a keymap file), with modifiers.
+///
+/// Modifiers that are not given (`None`) match either way.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "KeyChordSpec")]
+pub struct KeyChord {
+    pub keycode: Keycode,
+    pub shift: Option<bool>,
+    pub ctrl: Option<bool>,
+    pub alt: Option<bool>,
+    pub meta: Option<bool>,
+}
+
+/// A key chord as written in a keymap file, before its key name is parsed.
+#[derive(Deserialize)]
+struct KeyChordSpec {
+    keycode: String,
+    #[serde(default)]
+    shift: Option<bool>,
+    #[serde(default)]
+    ctrl: Option<bool>,
+    #[serde(default)]
+    alt: Option<bool>,
+    #[serde(default)]
+    meta: Option<bool>,
+}
+
+impl TryFrom<KeyChordSpec> for KeyChord {
+    type Error = String;
+    fn try_from(spec: KeyChordSpec) -> Result<Self, String> {
+        match Keycode::from_name(&spec.keycode) {
+            Some(keycode) => Ok(KeyChord {
+                keycode,
+                shift: spec.shift,
+                ctrl: spec.ctrl,
+                alt: spec.alt,
+                meta: spec.meta,
+            }),
+            None => Err(format!("unknown key name {:?}", spec.keycode)),
+        }
+    }
+}
+
+/// A key chord, and the key name (`KeyEventInfo.key`) to send for it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct KeyBinding {
+    pub chord: KeyChord,
+    pub key: String,
+}
+
+/// Keymap, from a keymap file (in RON syntax), e.g.:
+///
+/// ```text
+/// (
+///   quit: [(keycode: "F10")],
+///   keys: [
+///     (chord: (keycode: "Escape"), key: "Escape"),
+///     (chord: (keycode: "Q", ctrl: Some(true)), key: "q"),
+///   ],
+/// )
+/// ```
+///
+/// Bindings override the built-in key names of `translate_event`.
+/// In text-input mode, printable keys (not chorded with Ctrl or Meta) arrive as text,
+/// not as key events, so bindings for them have no effect.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Keymap {
+    /// Chords that quit the terminal; by default, Escape.
+    #[serde(default = "default_quit")]
+    pub quit: Vec<KeyChord>,
+    #[serde(default)]
+    pub keys: Vec<KeyBinding>,
+}
+
+fn default_quit() -> Vec<KeyChord> {
+    vec![KeyChord {
+        keycode: Keycode::Escape,
+        shift: None,
+        ctrl: None,
+        alt: None,
+        meta: None,
+    }]
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            quit: default_quit(),
+            keys: vec![],
+        }
+    }
+}
+
+/// Parse a keymap (in RON syntax), and check that it names only known keys.
+impl FromStr for Keymap {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        ron::de::from_str(s).map_err(|e| e.to_string())
+    }
+}
+
+impl KeyChord {
+    pub fn matches(&self, keycode: &Keycode, keymod: &Mod) -> bool {
+        let modifier = |m: Option<bool>, l: Mod, r: Mod| match m {
+            None => true,
+            Some(b) => b == (keymod.contains(l) || keymod.contains(r)),
+        };
+        self.keycode == *keycode
+            && modifier(self.shift, Mod::LSHIFTMOD, Mod::RSHIFTMOD)
+            && modifier(self.ctrl, Mod::LCTRLMOD, Mod::RCTRLMOD)
+            && modifier(self.alt, Mod::LALTMOD, Mod::RALTMOD)
+            && modifier(self.meta, Mod::LGUIMOD, Mod::RGUIMOD)
+    }
+}
+
+impl Keymap {
+    /// Read a keymap file.
+    pub fn from_file(path: &str) -> IcmtResult<Keymap> {
+        let text = std::fs::read_to_string(path)
+            .context(|| format!("cannot read keymap file {:?}", path))?;
+        text.parse()
+            .map_err(|e| IcmtError::String(format!("keymap file {}: {}", path, e)))
+    }
+
+    pub fn is_quit(&self, keycode: &Keycode, keymod: &Mod) -> bool {
+        self.quit.iter().any(|c| c.matches(keycode, keymod))
+    }
+
+    /// Translate the key, using the first binding that matches, or else the built-in names.
+    pub fn translate_event(&self, keycode: &Keycode, keymod: &Mod) -> Option<KeyEventInfo> {
+        match self.keys.iter().find(|b| b.chord.matches(keycode, keymod)) {
+            Some(binding) => Some(key_event_info(binding.key.clone(), keymod)),
+            None => translate_event(keycode, keymod),
+        }
+    }
+}
+
+/// Keyboard configuration, with the state that translation keeps across SDL events.
+#[derive(Debug, Clone)]
+pub struct Keyboard {
+    pub keymap: Keymap,
+    /// Take printable keys from SDL text input (see `translate_text`).
+    pub text_input: bool,
+    /// Report auto-repeated key presses.
+    pub repeats: bool,
+    /// Text key just pressed, awaiting its text input: its keycode, and whether it repeats.
+    /// (SDL text input says neither.)
+    pending_text_key: Option<(Keycode, bool)>,
+    /// Key name that each held key sent (as text input, or as a key event), to name its
+    /// release, even if its modifiers are released first.
+    held_keys: HashMap<Keycode, String>,
+}
+
+impl Keyboard {
+    pub fn new(keymap: Keymap, text_input: bool, repeats: bool) -> Keyboard {
+        Keyboard {
+            keymap,
+            text_input,
+            repeats,
+            pending_text_key: None,
+            held_keys: HashMap::new(),
+        }
+    }
+
+    pub fn is_quit(&self, keycode: &Keycode, keymod: &Mod) -> bool {
+        self.keymap.is_quit(keycode, keymod)
+    }
+
+    pub fn key_down(&mut self, keycode: &Keycode, keymod: &Mod, repeat: bool) -> Option<Event> {
+        if self.text_input && is_text_key(keycode, keymod) {
+            self.pending_text_key = Some((*keycode, repeat));
+            return None;
+        }
+        if repeat && !self.repeats {
+            return None;
+        }
+        let mut info = self.keymap.translate_event(keycode, keymod)?;
+        self.held_keys.insert(*keycode, info.key.clone());
+        info.repeat = Some(repeat);
+        Some(Event::KeyDown(vec![info]))
+    }
+
+    pub fn key_up(&mut self, keycode: &Keycode, keymod: &Mod) -> Option<Event> {
+        let info = match self.held_keys.remove(keycode) {
+            Some(key) => key_event_info(key, keymod),
+            // e.g., a dead key, whose text comes with the next key.
+            None if self.text_input && is_text_key(keycode, keymod) => return None,
+            None => self.keymap.translate_event(keycode, keymod)?,
+        };
+        Some(Event::KeyUp(vec![info]))
+    }
+
+    pub fn text(&mut self, text: &str, keymod: &Mod) -> Option<Event> {
+        let repeat = match self.pending_text_key.take() {
+            Some((keycode, repeat)) => {
+                self.held_keys.insert(keycode, text.to_string());
+                repeat
+            }
+            None => false,
+        };
+        if repeat && !self.repeats {
+            return None;
+        }
+        let mut infos = translate_text(text, keymod);
+        for info in infos.iter_mut() {
+            info.repeat = Some(repeat)
+        }
+        Some(Event::KeyDown(infos))
+    }
+}
+
+fn key_event_info(key: String, keymod: &Mod) -> KeyEventInfo {
+    KeyEventInfo {
+        key,
+        repeat: Some(false),
+        alt: keymod.contains(Mod::LALTMOD) || keymod.contains(Mod::RALTMOD),
+        ctrl: keymod.contains(Mod::LCTRLMOD) || keymod.contains(Mod::RCTRLMOD),
+        meta: keymod.contains(Mod::LGUIMOD) || keymod.contains(Mod::RGUIMOD),
+        shift: keymod.contains(Mod::LSHIFTMOD) || keymod.contains(Mod::RSHIFTMOD),
+    }
+}
+
+/// Does the key produce a character that, in text-input mode, arrives as SDL text input instead?
+///
+/// Keys chorded with Ctrl or Meta (Gui) produce no text input, so they remain key events.
+pub fn is_text_key(keycode: &Keycode, keymod: &Mod) -> bool {
+    let code = *keycode as i32;
+    let chorded = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD | Mod::LGUIMOD | Mod::RGUIMOD);
+    (0x20..0x7f).contains(&code) && !chorded
+}
+
+/// Translate (committed) SDL text input into key events, one per character.
+///
+/// Unlike `translate_event`, this respects the user's keyboard layout, dead keys and input method.
+pub fn translate_text(text: &str, keymod: &Mod) -> Vec<KeyEventInfo> {
+    text.chars()
+        .map(|c| key_event_info(c.to_string(), keymod))
+        .collect()
+}
 
 pub fn translate_event(keycode: &Keycode, keymod: &Mod) -> Option<KeyEventInfo> {
     /* Note: The analysis below encodes my US Mac Book Pro keyboard, almost completely. */
-    /* Longer-term, we need a more complex design to handle other mappings and corresponding keyboard variations. */
+    /* In text-input mode (the default), printable keys arrive via `translate_text` instead, */
+    /* and this analysis only names the non-printable keys, and keys chorded with Ctrl or Meta. */
 
     let shift = keymod.contains(sdl2::keyboard::Mod::LSHIFTMOD)
         || keymod.contains(sdl2::keyboard::Mod::RSHIFTMOD);
     let key = match &keycode {
+        Keycode::Escape => "Escape".to_string(),
         Keycode::Tab => "Tab".to_string(),
         Keycode::Space => " ".to_string(),
         Keycode::Return => "Enter".to_string(),
@@ -22,6 +269,24 @@ pub fn translate_event(keycode: &Keycode, keymod: &Mod) -> Option<KeyEventInfo>
         Keycode::Up => "ArrowUp".to_string(),
         Keycode::Down => "ArrowDown".to_string(),
         Keycode::Backspace => "Backspace".to_string(),
+        Keycode::Delete => "Delete".to_string(),
+        Keycode::Insert => "Insert".to_string(),
+        Keycode::Home => "Home".to_string(),
+        Keycode::End => "End".to_string(),
+        Keycode::PageUp => "PageUp".to_string(),
+        Keycode::PageDown => "PageDown".to_string(),
+        Keycode::F1 => "F1".to_string(),
+        Keycode::F2 => "F2".to_string(),
+        Keycode::F3 => "F3".to_string(),
+        Keycode::F4 => "F4".to_string(),
+        Keycode::F5 => "F5".to_string(),
+        Keycode::F6 => "F6".to_string(),
+        Keycode::F7 => "F7".to_string(),
+        Keycode::F8 => "F8".to_string(),
+        Keycode::F9 => "F9".to_string(),
+        Keycode::F10 => "F10".to_string(),
+        Keycode::F11 => "F11".to_string(),
+        Keycode::F12 => "F12".to_string(),
         Keycode::LShift => "Shift".to_string(),
         Keycode::Num0 => (if shift { ")" } else { "0" }).to_string(),
         Keycode::Num1 => (if shift { "!" } else { "1" }).to_string(),
@@ -82,9 +347,7 @@ pub fn translate_event(keycode: &Keycode, keymod: &Mod) -> Option<KeyEventInfo>
         Keycode::RightBracket => (if shift { "}" } else { "]" }).to_string(),
 
         /* More to consider later (but can we capture these in a browser?):
-        Escape --- (Already caught, to quit.)
         CapsLock --- Remapped to Control, for me at least.
-        F1--F12 --- Non-standard, but useful for experts' customization macros?
         Modifiers (??): LCtrl, LShift, LAlt, LGui, RCtrl, RShift, RAlt, RGui
          */
         keycode => {
@@ -92,12 +355,5 @@ pub fn translate_event(keycode: &Keycode, keymod: &Mod) -> Option<KeyEventInfo>
             return None;
         }
     };
-    let event = KeyEventInfo {
-        key: key,
-        alt: keymod.contains(Mod::LALTMOD) || keymod.contains(Mod::RALTMOD),
-        ctrl: keymod.contains(Mod::LCTRLMOD) || keymod.contains(Mod::RCTRLMOD),
-        meta: keymod.contains(Mod::LGUIMOD) || keymod.contains(Mod::RGUIMOD),
-        shift: keymod.contains(Mod::LSHIFTMOD) || keymod.contains(Mod::RSHIFTMOD),
-    };
-    Some(event)
+    Some(key_event_info(key, keymod))
 }
@@ -0,0 +1,454 @@
+//! Session state machine: which calls to make, and when, from local input and responses.
+//!
+//! The machine does no input or output of its own; a session feeds it `Input`s (local
+//! events, and the responses of its view and update tasks), and does the `Action`s that
+//! each step returns. At most one view call is in flight at a time, and at most
+//! `max_updates` update calls.
+//!
+//! Each update call sends a numbered batch of events. Live events get monotonically
+//! increasing nonces as they become pending (so views have them too), so that a service
+//! can tell which it did already. Batches stay unacknowledged until their calls are done;
+//! a batch whose call fails (transiently) is retransmitted, with the same events and
+//! nonces, or else (for services that do not deduplicate by nonce) kept as unconfirmed,
+//! and reported, but not sent again, since the service may have done it.
+//!
+//! Transitions, by phase:
+//!
+//! - `Running`: local events wait in `pending`, and are in each view call (after the
+//!   events of the unacknowledged batches). While fewer than `max_updates` update calls
+//!   are in flight, the next one sends all pending events; without calls in flight, it
+//!   sends even none, to get the service's commands.
+//!   `Quit`, or the end of the view task, goes to `Quitting`.
+//! - `Quitting`: local events are ignored. Update calls send the remaining pending
+//!   events, if any; once all batches are acknowledged, the machine is `Done`.
+//!   `Quit` again goes to `Done` at once, dropping the unacknowledged batches.
+//!   (Replays start here, with their events pending, sent in frames.)
+//! - `Done`: no more calls; the session finishes. The end of the update task goes here
+//!   from any phase, dropping pending events.
+
+use crate::connection::CallKind;
+use crate::types::{event, graphics, Nat};
+
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+
+/// Update calls send numbered batches of events (from 1), to match their responses.
+pub type BatchId = u64;
+
+/// Phase of a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Sending local events, in view and update calls.
+    Running,
+    /// Sending the last pending events, before finishing.
+    Quitting,
+    /// Finished: no more calls.
+    Done,
+}
+
+/// State of the calls to one of the service's tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallState {
+    Idle,
+    InFlight,
+    /// The task ended, e.g., after an error; no more calls.
+    Ended,
+}
+
+/// Inputs to the machine.
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// A local event, from the user.
+    Event(event::EventInfo),
+    /// The window size changed.
+    Resize(graphics::Dim),
+    /// The user asked to quit.
+    Quit,
+    /// The view call is done, with graphics.
+    ViewDone(graphics::Result),
+    /// The view call failed (transiently); the last good frame stays.
+    ViewFailed,
+    /// The update call of the batch is done, with its results: the batch is acknowledged.
+    UpdateDone(BatchId, Vec<graphics::Result>),
+    /// The update call of the batch failed (transiently).
+    UpdateFailed(BatchId),
+    /// The task for this kind of call ended.
+    TaskEnded(CallKind),
+}
+
+/// Actions for the session to do, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Make a view call, of the window size, with the events.
+    View(graphics::Dim, Vec<event::EventInfo>),
+    /// Make an update call, with a new batch of events.
+    Update(BatchId, Vec<event::EventInfo>, graphics::Request),
+    /// Make an update call again, with an unacknowledged batch.
+    Retransmit(BatchId, Vec<event::EventInfo>, graphics::Request),
+    /// Report the batch of a failed update call, not sent again: the service may or may
+    /// not have done its events.
+    Unconfirmed(BatchId, Vec<event::EventInfo>),
+    /// Draw the graphics.
+    Draw(graphics::Result),
+    /// Do the service's terminal commands.
+    Commands(Vec<graphics::Command>),
+    /// Capture the graphics, from an update call.
+    Capture(Vec<graphics::Result>),
+    /// Finish the session.
+    Finish,
+}
+
+/// Counts of calls and responses, for logging.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub view_requests: u64,
+    pub view_responses: u64,
+    pub view_failures: u64,
+    pub update_requests: u64,
+    pub update_responses: u64,
+    pub update_retransmits: u64,
+    pub update_unconfirmed: u64,
+}
+
+/// Session state machine.
+#[derive(Debug, Clone)]
+pub struct Machine {
+    phase: Phase,
+    view: CallState,
+    update_ended: bool,
+    /// Live sessions number their events, and do the service's commands; replays do not.
+    live: bool,
+    /// Retransmit the batches of failed update calls (otherwise, keep them as unconfirmed).
+    retransmit: bool,
+    /// Request all graphics with each update call (otherwise, none).
+    all_graphics: bool,
+    /// Most events in one update call.
+    frame_size: usize,
+    /// Most update calls in flight.
+    max_updates: usize,
+    dim: graphics::Dim,
+    next_batch: BatchId,
+    next_nonce: u64,
+    /// Batches sent in update calls, and not yet acknowledged.
+    unacked: BTreeMap<BatchId, Vec<event::EventInfo>>,
+    /// Batches of failed update calls, not retransmitted.
+    unconfirmed: BTreeMap<BatchId, Vec<event::EventInfo>>,
+    /// Events not yet sent in an update call.
+    pending: Vec<event::EventInfo>,
+    /// Events (or a resize) since the last view call.
+    stale: bool,
+    stats: Stats,
+}
+
+impl Machine {
+    /// Machine for a live session, whose first update call sends `first` (e.g., the
+    /// user's skip event, and the files to send).
+    pub fn live(first: Vec<event::EventInfo>, dim: graphics::Dim, all_graphics: bool) -> Self {
+        Machine {
+            phase: Phase::Running,
+            view: CallState::Idle,
+            update_ended: false,
+            live: true,
+            retransmit: true,
+            all_graphics,
+            frame_size: usize::MAX,
+            max_updates: 1,
+            dim,
+            next_batch: 1,
+            next_nonce: 0,
+            unacked: BTreeMap::new(),
+            unconfirmed: BTreeMap::new(),
+            pending: first,
+            stale: true,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Machine for a replay of the events, in update calls of (at most) `frame_size` events.
+    ///
+    /// Replayed events keep their nonces, and their update calls are in order, one at a time.
+    pub fn replay(events: Vec<event::EventInfo>, frame_size: usize, dim: graphics::Dim) -> Self {
+        Machine {
+            phase: Phase::Quitting,
+            view: CallState::Idle,
+            update_ended: false,
+            live: false,
+            retransmit: true,
+            all_graphics: true,
+            frame_size: frame_size.max(1),
+            max_updates: 1,
+            dim,
+            next_batch: 1,
+            next_nonce: 0,
+            unacked: BTreeMap::new(),
+            unconfirmed: BTreeMap::new(),
+            pending: events,
+            stale: true,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Allow up to `n` update calls in flight (live sessions only).
+    pub fn max_updates(mut self, n: usize) -> Self {
+        if self.live {
+            self.max_updates = n.max(1);
+        }
+        self
+    }
+
+    /// Number events from this nonce on (e.g., from the session's start time, so that
+    /// nonces keep increasing across sessions).
+    pub fn first_nonce(mut self, nonce: u64) -> Self {
+        self.next_nonce = nonce;
+        self
+    }
+
+    /// Retransmit the batches of failed update calls, or else keep them as unconfirmed
+    /// (for services that may do a batch twice).
+    pub fn retransmit(mut self, retransmit: bool) -> Self {
+        self.retransmit = retransmit;
+        self
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn view_state(&self) -> CallState {
+        self.view
+    }
+
+    pub fn update_state(&self) -> CallState {
+        if self.update_ended {
+            CallState::Ended
+        } else if self.unacked.is_empty() {
+            CallState::Idle
+        } else {
+            CallState::InFlight
+        }
+    }
+
+    /// Number of update calls in flight (unacknowledged batches).
+    pub fn updates_in_flight(&self) -> usize {
+        self.unacked.len()
+    }
+
+    /// Batches of failed update calls, that were not retransmitted.
+    pub fn unconfirmed(&self) -> &BTreeMap<BatchId, Vec<event::EventInfo>> {
+        &self.unconfirmed
+    }
+
+    /// Current window size.
+    pub fn dim(&self) -> &graphics::Dim {
+        &self.dim
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// First actions: the first update and view calls.
+    pub fn start(&mut self) -> Vec<Action> {
+        let mut first = std::mem::take(&mut self.pending);
+        for ev in first.iter_mut() {
+            self.number(ev);
+        }
+        self.pending = first;
+        let mut actions = vec![];
+        self.next_updates(&mut actions);
+        self.next_view(&mut actions);
+        actions
+    }
+
+    /// Step the machine with an input, giving the actions to do.
+    pub fn step(&mut self, input: Input) -> Vec<Action> {
+        let mut actions = vec![];
+        if self.phase == Phase::Done {
+            debug!("Session done; ignoring {:?}", input);
+            return actions;
+        }
+        match input {
+            Input::Event(mut ev) => {
+                if self.phase == Phase::Running {
+                    self.number(&mut ev);
+                    self.pending.push(ev);
+                    self.stale = true;
+                } else {
+                    debug!("Quitting; ignoring event {:?}", ev.event);
+                }
+            }
+            Input::Resize(dim) => {
+                self.dim = dim;
+                self.stale = true;
+            }
+            Input::Quit => {
+                if self.phase == Phase::Running {
+                    info!("Quitting, after {} pending event(s).", self.pending.len());
+                    self.phase = Phase::Quitting;
+                } else {
+                    let dropped = self.drop_events();
+                    info!("Quitting again; dropping {} event(s).", dropped);
+                    self.phase = Phase::Done;
+                }
+            }
+            Input::ViewDone(rr) => {
+                self.view_returned();
+                self.stats.view_responses += 1;
+                actions.push(Action::Draw(rr));
+            }
+            Input::ViewFailed => {
+                self.view_returned();
+                self.stats.view_failures += 1;
+                self.stale = true;
+            }
+            Input::UpdateDone(batch, results) => {
+                if self.unacked.remove(&batch).is_none() {
+                    debug!("Update response for batch {}, already acknowledged.", batch);
+                    return actions;
+                }
+                self.stats.update_responses += 1;
+                self.stale = true;
+                if self.live {
+                    for rr in results.iter() {
+                        if let graphics::Result::Ok(graphics::Out::Commands(cmds)) = rr {
+                            actions.push(Action::Commands(cmds.clone()));
+                        }
+                    }
+                }
+                actions.push(Action::Capture(results));
+            }
+            Input::UpdateFailed(batch) if !self.retransmit => match self.unacked.remove(&batch) {
+                Some(events) => {
+                    warn!(
+                        "Batch {} ({} event(s)) unconfirmed; not sending it again, since the \
+                         service may have done it.",
+                        batch,
+                        events.len()
+                    );
+                    self.stats.update_unconfirmed += 1;
+                    self.stale = true;
+                    self.unconfirmed.insert(batch, events.clone());
+                    actions.push(Action::Unconfirmed(batch, events));
+                }
+                None => debug!("Update failure for batch {}, already acknowledged.", batch),
+            },
+            Input::UpdateFailed(batch) => match self.unacked.get(&batch) {
+                Some(events) => {
+                    info!(
+                        "Retransmitting batch {} ({} event(s)).",
+                        batch,
+                        events.len()
+                    );
+                    self.stats.update_retransmits += 1;
+                    actions.push(Action::Retransmit(
+                        batch,
+                        events.clone(),
+                        self.update_request(),
+                    ));
+                }
+                None => debug!("Update failure for batch {}, already acknowledged.", batch),
+            },
+            Input::TaskEnded(CallKind::View) => {
+                self.view = CallState::Ended;
+                if self.phase == Phase::Running {
+                    info!("View task ended; quitting.");
+                    self.phase = Phase::Quitting;
+                }
+            }
+            Input::TaskEnded(CallKind::Update) => {
+                self.update_ended = true;
+                let dropped = self.drop_events();
+                if dropped > 0 {
+                    info!("Update task ended; dropping {} event(s).", dropped);
+                }
+                self.phase = Phase::Done;
+            }
+        }
+        self.next_updates(&mut actions);
+        self.next_view(&mut actions);
+        if self.phase == Phase::Done {
+            info!("Session done: {:?}", self.stats);
+            actions.push(Action::Finish);
+        }
+        actions
+    }
+
+    /// Drop the pending events, and the unacknowledged batches; gives how many events.
+    fn drop_events(&mut self) -> usize {
+        let dropped = self.pending.len() + self.unacked.values().map(Vec::len).sum::<usize>();
+        self.pending.clear();
+        self.unacked.clear();
+        dropped
+    }
+
+    /// Give a live event its nonce.
+    fn number(&mut self, ev: &mut event::EventInfo) {
+        if self.live {
+            ev.nonce = Some(Nat::from(self.next_nonce));
+            self.next_nonce += 1;
+        }
+    }
+
+    fn view_returned(&mut self) {
+        if self.view == CallState::InFlight {
+            self.view = CallState::Idle;
+        }
+    }
+
+    fn update_request(&self) -> graphics::Request {
+        if self.all_graphics {
+            graphics::Request::All(self.dim.clone())
+        } else {
+            graphics::Request::None
+        }
+    }
+
+    /// Make the next update calls, while there are free slots for them; or else, finish
+    /// quitting.
+    fn next_updates(&mut self, actions: &mut Vec<Action>) {
+        if self.update_ended {
+            return;
+        }
+        while self.unacked.len() < self.max_updates {
+            match self.phase {
+                Phase::Done => return,
+                Phase::Quitting if self.pending.is_empty() => {
+                    if self.unacked.is_empty() {
+                        self.phase = Phase::Done;
+                    }
+                    return;
+                }
+                Phase::Running if self.pending.is_empty() && !self.unacked.is_empty() => return,
+                Phase::Running | Phase::Quitting => {}
+            }
+            let n = self.frame_size.min(self.pending.len());
+            let events: Vec<event::EventInfo> = self.pending.drain(..n).collect();
+            let batch = self.next_batch;
+            self.next_batch += 1;
+            self.unacked.insert(batch, events.clone());
+            self.stats.update_requests += 1;
+            actions.push(Action::Update(batch, events, self.update_request()));
+        }
+    }
+
+    /// Make the next view call, if the view is stale and the view task is idle.
+    ///
+    /// Live views have the events that the service has not done yet: those of the
+    /// unacknowledged batches, then those pending. Replay views have none.
+    fn next_view(&mut self, actions: &mut Vec<Action>) {
+        if self.phase == Phase::Done || self.view != CallState::Idle || !self.stale {
+            return;
+        }
+        let mut events = vec![];
+        if self.live {
+            for batch in self.unacked.values() {
+                events.extend_from_slice(batch);
+            }
+            events.extend_from_slice(&self.pending);
+        }
+        self.stale = false;
+        self.view = CallState::InFlight;
+        self.stats.view_requests += 1;
+        actions.push(Action::View(self.dim.clone(), events));
+    }
+}
@@ -5,14 +5,26 @@
 extern crate log;
 //extern crate hashcons;
 extern crate candid;
+extern crate icmt_core;
 extern crate serde;
 extern crate serde_bytes;
 //extern crate candid_derive;
 
 pub mod cli;
 pub mod color;
+pub mod config;
+pub mod connection;
+pub mod counter;
+pub mod dfx;
 pub mod draw;
 pub mod error;
+pub mod identity;
 pub mod keyboard;
+pub mod machine;
+pub mod mouse;
+pub mod network;
+pub mod service;
+pub mod session;
 pub mod types;
+pub mod window;
 pub mod write;
@@ -0,0 +1,37 @@
+//! Mouse events.
+
+use sdl2::mouse::MouseButton;
+
+use super::types::{event, graphics};
+
+use log::info;
+
+/// Position in window coordinates; positions left of or above the window clamp to zero.
+pub fn translate_pos(x: i32, y: i32) -> graphics::Pos {
+    graphics::Pos::new(x.max(0) as u32, y.max(0) as u32)
+}
+
+pub fn translate_button(button: &MouseButton) -> Option<event::MouseButton> {
+    match button {
+        MouseButton::Left => Some(event::MouseButton::Left),
+        MouseButton::Middle => Some(event::MouseButton::Middle),
+        MouseButton::Right => Some(event::MouseButton::Right),
+        MouseButton::X1 => Some(event::MouseButton::X1),
+        MouseButton::X2 => Some(event::MouseButton::X2),
+        MouseButton::Unknown => {
+            info!("Unrecognized mouse button, ignoring event.");
+            None
+        }
+    }
+}
+
+pub fn translate_button_event(
+    button: &MouseButton,
+    x: i32,
+    y: i32,
+) -> Option<event::MouseButtonInfo> {
+    Some(event::MouseButtonInfo {
+        pos: translate_pos(x, y),
+        button: translate_button(button)?,
+    })
+}
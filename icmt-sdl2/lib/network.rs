@@ -0,0 +1,117 @@
+//! Networks (replicas) to connect to, and how to trust their root keys.
+
+use crate::error::{Context, IcmtError, IcmtResult};
+
+use std::str::FromStr;
+
+/// URL of the local replica, as started by `dfx start`.
+pub const LOCAL_URL: &str = "http://127.0.0.1:8000";
+
+/// URL of the IC (mainnet).
+pub const IC_URL: &str = "https://ic0.app";
+
+/// Network to connect to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Network {
+    /// The local replica (of `dfx start`).
+    Local,
+    /// The IC (mainnet).
+    Ic,
+    /// A network named in the dfx project's `dfx.json`.
+    Named(String),
+    /// A replica at some URL.
+    Url(String),
+}
+
+/// Network names are dfx's: letters, digits, `-` and `_`.
+fn is_network_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl FromStr for Network {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "local" => Ok(Network::Local),
+            "ic" => Ok(Network::Ic),
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                Ok(Network::Url(url.to_string()))
+            }
+            name if is_network_name(name) => Ok(Network::Named(name.to_string())),
+            _ => Err(format!(
+                "invalid network {:?}; expected local, ic, a network name or an http(s):// URL",
+                s
+            )),
+        }
+    }
+}
+
+impl Network {
+    /// Local networks are on this machine: the local replica, or a URL with a loopback host
+    /// (named networks are local once resolved to such a URL).
+    pub fn is_local(&self) -> bool {
+        match self {
+            Network::Local => true,
+            Network::Ic | Network::Named(_) => false,
+            Network::Url(url) => {
+                let rest = url.splitn(2, "://").last().unwrap_or("");
+                let authority = rest.split('/').next().unwrap_or("");
+                let host = if authority.starts_with('[') {
+                    authority.split(']').next().unwrap_or("").trim_start_matches('[')
+                } else {
+                    authority.split(':').next().unwrap_or("")
+                };
+                matches!(host, "localhost" | "127.0.0.1" | "0.0.0.0" | "::1")
+            }
+        }
+    }
+}
+
+/// How the agent comes to trust the network's root key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootKey {
+    /// The IC's root key, built into the agent.
+    Builtin,
+    /// Fetched from the replica (trusting it); only for local or explicitly insecure targets.
+    Fetch,
+    /// Pinned (DER-encoded) key, from a file.
+    Pinned(Vec<u8>),
+}
+
+/// Choose how to trust the root key.
+///
+/// A pinned key file wins; otherwise, the key is fetched only from a local network,
+/// or when `insecure_fetch` says to.
+pub fn root_key(
+    network: &Network,
+    root_key_file: &Option<String>,
+    insecure_fetch: bool,
+) -> IcmtResult<RootKey> {
+    if let Some(path) = root_key_file {
+        return Ok(RootKey::Pinned(read_root_key(path)?));
+    }
+    if network.is_local() || insecure_fetch {
+        Ok(RootKey::Fetch)
+    } else {
+        Ok(RootKey::Builtin)
+    }
+}
+
+/// Read a (DER-encoded) root key file, either raw or as hex text.
+pub fn read_root_key(path: &str) -> IcmtResult<Vec<u8>> {
+    let path = shellexpand::tilde(path).into_owned();
+    let bytes = std::fs::read(&path).context(|| format!("cannot read root key file {:?}", path))?;
+    let key = match std::str::from_utf8(&bytes) {
+        Ok(text) => match hex::decode(text.trim()) {
+            Ok(key) => key,
+            Err(_) => bytes.clone(),
+        },
+        Err(_) => bytes.clone(),
+    };
+    if key.is_empty() {
+        return Err(IcmtError::String(format!("empty root key file {:?}", path)));
+    }
+    Ok(key)
+}
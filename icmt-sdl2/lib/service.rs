@@ -0,0 +1,271 @@
+//! Service backends, for the mini terminal's view/update protocol.
+//!
+//! The terminal talks to an IC canister through an agent (`AgentService`), or to an
+//! in-process Rust implementation (e.g., `counter::CounterService`), for testing without
+//! a replica.
+
+use crate::cli::ConnectCfg;
+use crate::error::{exit_code, Context, ExitCode, IcmtError, IcmtResult};
+use crate::identity;
+use crate::network::RootKey;
+use crate::types::{event, graphics, ServiceCall};
+
+use async_trait::async_trait;
+use candid::Decode;
+use chrono::prelude::*;
+use ic_agent::agent::http_transport::ReqwestHttpReplicaV2Transport;
+use ic_agent::Agent;
+use ic_types::Principal;
+use icmt_core::check::check_graphics;
+use log::{info, trace, warn};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const RETRY_PAUSE: Duration = Duration::from_millis(100);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A service that speaks the mini terminal's protocol (see `service.did`).
+#[async_trait]
+pub trait Service: Send + Sync + std::fmt::Debug {
+    /// Query a projected view: the service's state, with the given (pending) events.
+    async fn view(
+        &self,
+        dim: graphics::Dim,
+        events: Vec<event::EventInfo>,
+    ) -> IcmtResult<graphics::Result>;
+
+    /// Update the service's state with the events; results have the requested graphics.
+    async fn update(
+        &self,
+        events: Vec<event::EventInfo>,
+        req: graphics::Request,
+    ) -> IcmtResult<Vec<graphics::Result>>;
+
+    /// Does the service do each event at most once, by its nonce? Then a batch whose
+    /// update call failed (perhaps after the service did it) can be sent again.
+    fn dedupes_nonces(&self) -> bool {
+        false
+    }
+
+    /// Does the service do events in nonce order, whatever the order of its update calls?
+    /// Then several update calls can be in flight at once.
+    fn orders_nonces(&self) -> bool {
+        false
+    }
+}
+
+/// Do a service call, with the service.
+pub async fn call(service: &dyn Service, call: ServiceCall) -> IcmtResult<Vec<graphics::Result>> {
+    match call {
+        ServiceCall::FlushQuit => Ok(vec![]),
+        ServiceCall::View(dim, events) => Ok(vec![service.view(dim, events).await?]),
+        ServiceCall::Update(events, req) => service.update(events, req).await,
+    }
+}
+
+/// In-process service, by name.
+pub fn in_process(name: &str) -> IcmtResult<Arc<dyn Service>> {
+    match name {
+        "counter" => Ok(Arc::new(crate::counter::CounterService::new())),
+        _ => Err(IcmtError::String(format!(
+            "no in-process service named {:?} (try counter)",
+            name
+        ))),
+    }
+}
+
+/// Create an agent, signing calls with the identity in the PEM file (or the selected identity).
+pub async fn create_agent(
+    url: &str,
+    root_key: &RootKey,
+    pem_file: &Option<PathBuf>,
+) -> IcmtResult<Agent> {
+    let pem_path = match pem_file {
+        Some(pem_path) => pem_path.clone(),
+        None => identity::selected_pem_path()?,
+    };
+    let identity = identity::load(&pem_path)?;
+    let transport = ReqwestHttpReplicaV2Transport::create(url)?;
+    let agent = Agent::builder()
+        .with_transport(transport)
+        .with_identity(identity)
+        .build()?;
+    info!("built agent.");
+    match root_key {
+        RootKey::Builtin => (),
+        RootKey::Fetch => {
+            agent.fetch_root_key().await?;
+            info!("got root key.");
+        }
+        RootKey::Pinned(key) => {
+            agent.set_root_key(key.clone())?;
+            info!("set pinned root key.");
+        }
+    }
+    Ok(agent)
+}
+
+/// IC canister, called through an agent.
+pub struct AgentService {
+    pub agent: Agent,
+    pub canister_id: Principal,
+    /// CSV file for profiling view calls (call time, response size, decoding time).
+    pub data_path: PathBuf,
+    /// Log (truncated) graphics output, at trace level.
+    pub log_trace: bool,
+    /// The canister dedupes and orders events by nonce, as configured (canisters do not say).
+    pub nonces: bool,
+}
+
+impl std::fmt::Debug for AgentService {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AgentService")
+            .field("canister_id", &self.canister_id)
+            .field("data_path", &self.data_path)
+            .finish()
+    }
+}
+
+impl AgentService {
+    /// Connect to the configured canister, with a new agent.
+    pub async fn connect(cfg: &ConnectCfg, data_path: PathBuf) -> IcmtResult<AgentService> {
+        let canister_id = Principal::from_text(cfg.canister_id.clone()).map_err(|e| {
+            IcmtError::String(format!("invalid canister ID {:?}: {}", cfg.canister_id, e))
+        })?;
+        let agent = create_agent(&cfg.replica_url, &cfg.root_key, &cfg.pem_file).await?;
+        Ok(AgentService {
+            agent,
+            canister_id,
+            data_path,
+            log_trace: cfg.cli_opt.log_trace,
+            nonces: cfg.cli_opt.canister_nonces,
+        })
+    }
+}
+
+#[async_trait]
+impl Service for AgentService {
+    async fn view(
+        &self,
+        dim: graphics::Dim,
+        events: Vec<event::EventInfo>,
+    ) -> IcmtResult<graphics::Result> {
+        let prefix = "Service (view):";
+        let mut data_file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.data_path)
+            .context(|| format!("cannot open profile data file {:?}", self.data_path))
+            .with_exit_code(exit_code::IO)?;
+        let arg_bytes = candid::encode_args((dim, events)).with_exit_code(exit_code::CANDID)?;
+        info!(
+            "{}: Encoded argument via Candid; Arg size {:?} bytes",
+            prefix,
+            arg_bytes.len()
+        );
+        info!("{}: Awaiting response from service...", prefix);
+        let timestamp = SystemTime::now();
+        let blob_res = self
+            .agent
+            .query(&self.canister_id, "view")
+            .with_arg(arg_bytes)
+            .call()
+            .await
+            .context(|| format!("calling view on canister {}", self.canister_id))?;
+        let call_elapsed = timestamp.elapsed().unwrap();
+        info!(
+            "{}: Ok: Response size {:?} bytes; elapsed time {:?}",
+            prefix,
+            blob_res.len(),
+            call_elapsed
+        );
+        let decode_size = blob_res.len();
+        let decode_begin = SystemTime::now();
+        let res = candid::Decode!(&(*blob_res), graphics::Result)
+            .context(|| format!("decoding view result from canister {}", self.canister_id))
+            .with_exit_code(exit_code::CANDID)?;
+        if let Err(e) = check_graphics(&res) {
+            warn!("{}: Invalid graphics output: {}", prefix, e);
+        }
+        if self.log_trace {
+            let mut res_log = format!("{:?}", &res);
+            if res_log.len() > 1000 {
+                res_log.truncate(1000);
+                res_log.push_str("...(truncated)");
+            }
+            trace!(
+                "{}: Successful decoding of graphics output: {:?}",
+                prefix,
+                res_log
+            );
+        }
+        let decode_elapsed = decode_begin.elapsed().unwrap();
+        info!(
+            "{:?} bytes of Candid decoded in elapsed time {:?}",
+            decode_size, decode_elapsed
+        );
+        data_file
+            .write_all(
+                format!(
+                    "{}, {}, {}, {}\n",
+                    Local::now().to_rfc3339(),
+                    call_elapsed.as_nanos(),
+                    decode_size,
+                    decode_elapsed.as_nanos(),
+                )
+                .as_bytes(),
+            )
+            .and_then(|()| data_file.sync_all())
+            .context(|| format!("cannot write profile data file {:?}", self.data_path))
+            .with_exit_code(exit_code::IO)?;
+        Ok(res)
+    }
+
+    async fn update(
+        &self,
+        events: Vec<event::EventInfo>,
+        req: graphics::Request,
+    ) -> IcmtResult<Vec<graphics::Result>> {
+        let prefix = "Service (update):";
+        let delay = garcon::Delay::builder()
+            .throttle(RETRY_PAUSE)
+            .timeout(REQUEST_TIMEOUT)
+            .build();
+        let arg_bytes = candid::encode_args((events, req)).with_exit_code(exit_code::CANDID)?;
+        info!(
+            "{}: Encoded argument via Candid; Arg size {:?} bytes",
+            prefix,
+            arg_bytes.len()
+        );
+        info!("{}: Awaiting response from service...", prefix);
+        let timestamp = SystemTime::now();
+        let blob_res = self
+            .agent
+            .update(&self.canister_id, "update")
+            .with_arg(arg_bytes)
+            .call_and_wait(delay)
+            .await
+            .context(|| format!("calling update on canister {}", self.canister_id))?;
+        info!(
+            "{}: Ok: Response size {:?} bytes; elapsed time {:?}",
+            prefix,
+            blob_res.len(),
+            timestamp.elapsed().unwrap()
+        );
+        let res = candid::Decode!(&(*blob_res), Vec<graphics::Result>)
+            .context(|| format!("decoding update result from canister {}", self.canister_id))
+            .with_exit_code(exit_code::CANDID)?;
+        Ok(res)
+    }
+
+    fn dedupes_nonces(&self) -> bool {
+        self.nonces
+    }
+
+    fn orders_nonces(&self) -> bool {
+        self.nonces
+    }
+}
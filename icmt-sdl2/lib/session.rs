@@ -0,0 +1,849 @@
+//! Sessions: the mini terminal's loop, between local input, the service and outputs.
+//!
+//! A session sends local input events to a service backend (in view and update calls,
+//! each kind from its own task), and renders the service's graphics to its sinks (e.g.,
+//! a window), with hooks to capture the session (e.g., as GIF files).
+//!
+//! The `ic-mt` binary builds its sessions from the command line; other tools can embed
+//! the terminal too, e.g., a test harness with scripted input and an in-process service:
+//!
+//! ```ignore
+//! let session = Session::new(cfg)
+//!     .service(Arc::new(CounterService::new()))
+//!     .input(Box::new(scripted_input))
+//!     .sink(Box::new(recording_sink));
+//! session.run().await?;
+//! ```
+
+use crate::cli::{CliCommand, ConnectCfg};
+use crate::connection::{Backoff, CallKind, Connection, ConnectionState};
+use crate::draw::Layers;
+use crate::error::{exit_code, Context, ExitCode, IcmtError, IcmtResult};
+use crate::machine::{Action, BatchId, CallState, Input, Machine, Phase};
+use crate::service::{self, AgentService, Service};
+use crate::types::{
+    event, file_read_event, graphics, local_event, skip_event, Nat, ServiceCall, UserKind,
+};
+
+use async_trait::async_trait;
+use futures::future::FutureExt;
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{debug, error, info, trace, warn};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task;
+
+/// Window size, unless configured otherwise.
+pub const DEFAULT_WINDOW_SIZE: (u32, u32) = (500, 400);
+
+/// Poll interval for spectators, unless configured otherwise.
+const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Longest wait for input or responses, between polls of the input source.
+const POLL_WAIT: Duration = Duration::from_millis(13);
+
+/// Longest wait for a task to end, once the session is done with it (e.g., while the
+/// task still retries connecting).
+const TASK_END_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// CSV files for profiling service calls.
+const VIEW_PROFILE_PATH: &str = "out/icmt-profile-view.csv";
+const UPDATE_PROFILE_PATH: &str = "out/icmt-profile-update.csv";
+
+/// Source of local input events (e.g., a window's keyboard and mouse).
+pub trait InputSource {
+    /// The next event, without waiting; `None` if there is none (yet).
+    fn poll(&mut self) -> IcmtResult<Option<event::Event>>;
+}
+
+/// Output for the service's graphics and terminal commands (e.g., a window).
+#[async_trait(?Send)]
+pub trait RenderSink {
+    /// Render the layers; their damage gives what changed since the last render.
+    async fn render(&mut self, dim: &graphics::Dim, layers: &Layers) -> IcmtResult<()>;
+
+    /// Do a terminal command from the service.
+    fn command(&mut self, _cmd: &graphics::Command) -> IcmtResult<()> {
+        Ok(())
+    }
+
+    /// Show the connection state (e.g., in a window title).
+    fn connection(&mut self, _connection: &Connection) {}
+}
+
+/// Hooks to capture a session (e.g., to files).
+#[async_trait(?Send)]
+pub trait CaptureHook {
+    /// Events, as sent to the service.
+    fn events(&mut self, _events: &[event::EventInfo]) {}
+
+    /// Graphics, from the service's update calls.
+    fn graphics(&mut self, _graphics: &[graphics::Result]) {}
+
+    /// A rendered frame.
+    async fn frame(&mut self, _dim: &graphics::Dim, _layers: &Layers) -> IcmtResult<()> {
+        Ok(())
+    }
+
+    /// The window size changed, from `old` to `new`.
+    fn resize(&mut self, _old: &graphics::Dim, _new: &graphics::Dim) -> IcmtResult<()> {
+        Ok(())
+    }
+
+    /// The session ended, with the window at the given size.
+    fn finish(&mut self, _dim: &graphics::Dim) -> IcmtResult<()> {
+        Ok(())
+    }
+}
+
+/// An interactive session (or a replay, or a spectator's), with its configuration,
+/// input source, render sinks, capture hooks and (optionally) service backend.
+pub struct Session {
+    cfg: ConnectCfg,
+    dim: graphics::Dim,
+    input: Option<Box<dyn InputSource>>,
+    outputs: Outputs,
+}
+
+impl Session {
+    /// Session for the configuration, without input or outputs (yet).
+    pub fn new(cfg: ConnectCfg) -> Self {
+        let (width, height) = cfg.cli_opt.window_size.unwrap_or(DEFAULT_WINDOW_SIZE);
+        Session {
+            cfg,
+            dim: graphics::Dim::new(width, height),
+            input: None,
+            outputs: Outputs::new(),
+        }
+    }
+
+    /// Initial window size.
+    pub fn dim(&self) -> &graphics::Dim {
+        &self.dim
+    }
+
+    pub fn input(mut self, input: Box<dyn InputSource>) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn sink(mut self, sink: Box<dyn RenderSink>) -> Self {
+        self.outputs.sinks.push(sink);
+        self
+    }
+
+    pub fn capture(mut self, hook: Box<dyn CaptureHook>) -> Self {
+        self.outputs.hooks.push(hook);
+        self
+    }
+
+    /// Call this service backend, instead of the configured one.
+    pub fn service(mut self, service: Arc<dyn Service>) -> Self {
+        self.cfg.service = Some(service);
+        self
+    }
+
+    /// Run the session, until the user quits (or the replay ends); fails if one of its
+    /// tasks failed (e.g., its service calls).
+    pub async fn run(self) -> IcmtResult<()> {
+        trace!("{:?}", self.cfg);
+        match self.cfg.user_kind {
+            UserKind::Spectator => self.run_spectator().await,
+            UserKind::Local(_) | UserKind::Replay(_) => self.run_local().await,
+        }
+    }
+
+    /// Watch the service's view, without sending input or making update calls.
+    ///
+    /// Polls the view at the given interval, and redraws only when it changes.
+    async fn run_spectator(self) -> IcmtResult<()> {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let Session {
+            cfg,
+            mut dim,
+            mut input,
+            mut outputs,
+        } = self;
+        let poll_interval = match &cfg.cli_opt.command {
+            CliCommand::Watch { interval_ms, .. } => Duration::from_millis(*interval_ms),
+            _ => DEFAULT_WATCH_INTERVAL,
+        };
+
+        let (status_out, mut status_in) = unbounded_channel::<(CallKind, ConnectionState)>();
+        let (mut view_in, view_out, view_task) = {
+            let (local_out, remote_in) = unbounded_channel::<Option<ViewCall>>();
+            let (remote_out, local_in) = unbounded_channel::<Option<graphics::Result>>();
+            let view_task = task::spawn(do_view_task(
+                cfg.clone(),
+                remote_in,
+                remote_out,
+                status_out,
+                PathBuf::from(VIEW_PROFILE_PATH),
+            ));
+            local_out.send(Some((dim.clone(), vec![])))?;
+            (local_in, local_out, view_task)
+        };
+        let mut last_poll = Instant::now();
+        let mut last_hash: Option<u64> = None;
+        let mut in_flight = true;
+
+        'watching: loop {
+            match poll_input(&mut input)? {
+                Some(event::Event::Quit) => break 'watching,
+                Some(event::Event::WindowSize(new_dim)) => {
+                    outputs.resize(&dim, &new_dim)?;
+                    dim = new_dim;
+                    last_hash = None; // redraw the next view, even if unchanged.
+                }
+                _ => {}
+            }
+            while let Ok((kind, state)) = try_recv(&mut status_in) {
+                outputs.show_connection(kind, state);
+            }
+            let mut view_msg = try_recv(&mut view_in);
+            if let Err(TryRecvError::Empty) = view_msg {
+                tokio::select! {
+                    msg = view_in.recv() => view_msg = msg.ok_or(TryRecvError::Disconnected),
+                    _ = tokio::time::sleep(POLL_WAIT) => {}
+                }
+            }
+            match view_msg {
+                Ok(None) => {
+                    // failed view: keep the last good frame, and poll again.
+                    in_flight = false;
+                }
+                Ok(Some(rr)) => {
+                    in_flight = false;
+                    let mut hasher = DefaultHasher::new();
+                    rr.hash(&mut hasher);
+                    let hash = hasher.finish();
+                    if last_hash == Some(hash) {
+                        trace!("view unchanged");
+                    } else {
+                        last_hash = Some(hash);
+                        outputs.redraw(&dim, rr).await?;
+                    }
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    error!("View task ended; stopping.");
+                    break 'watching;
+                }
+            }
+            if !in_flight && last_poll.elapsed() >= poll_interval {
+                last_poll = Instant::now();
+                in_flight = true;
+                view_out.send(Some((dim.clone(), vec![])))?;
+            }
+        }
+        outputs.finish(&dim)?;
+        let _ = view_out.send(None); // (the view task may have ended already)
+        join_task(CallKind::View, view_task).await
+    }
+
+    /// Send local input (or replay events) to the service, and show its graphics.
+    async fn run_local(self) -> IcmtResult<()> {
+        let Session {
+            cfg,
+            dim,
+            mut input,
+            mut outputs,
+        } = self;
+        // (canisters do not say how they treat nonces; `AgentService` has the opt-in.)
+        let (dedupes, orders) = match &cfg.service {
+            Some(service) => (service.dedupes_nonces(), service.orders_nonces()),
+            None => (cfg.cli_opt.canister_nonces, cfg.cli_opt.canister_nonces),
+        };
+        let max_updates = if orders {
+            cfg.cli_opt.max_updates
+        } else {
+            if cfg.cli_opt.max_updates > 1 {
+                warn!(
+                    "The service does not order events by nonce (see --canister-nonces); \
+                     one update call at a time."
+                );
+            }
+            1
+        };
+        let mut machine = match (&cfg.cli_opt.command, &cfg.user_kind) {
+            (CliCommand::Replay { frame_size, .. }, UserKind::Replay(evs)) => {
+                Machine::replay(evs.clone(), *frame_size, dim).retransmit(dedupes)
+            }
+            (_, UserKind::Replay(evs)) => {
+                Machine::replay(evs.clone(), evs.len(), dim).retransmit(dedupes)
+            }
+            (_, _) => {
+                let mut first: Vec<_> = skip_event(&cfg).into_iter().collect();
+                for path in cfg.send_files.iter() {
+                    let event =
+                        file_read_event(path).context(|| format!("cannot read file {:?}", path))?;
+                    info!("Sending file {} with initial update.", path);
+                    first.extend(local_event(&cfg, event));
+                }
+                Machine::live(first, dim, cfg.cli_opt.all_graphics)
+                    .max_updates(max_updates)
+                    .retransmit(dedupes)
+                    .first_nonce(first_nonce())
+            }
+        };
+
+        // Interaction cycle as two halves (local/remote); the remote half is two tasks,
+        // for view calls and for update calls, each with a channel in and out.
+        let (status_out, mut status_in) = unbounded_channel::<(CallKind, ConnectionState)>();
+        let (update_out, remote_in) = unbounded_channel::<Option<UpdateCall>>();
+        let (remote_out, mut update_in) = unbounded_channel::<UpdateResponse>();
+        let update_task = task::spawn(do_update_task(
+            cfg.clone(),
+            remote_in,
+            remote_out,
+            status_out.clone(),
+            PathBuf::from(UPDATE_PROFILE_PATH),
+        ));
+        let (view_out, remote_in) = unbounded_channel::<Option<ViewCall>>();
+        let (remote_out, mut view_in) = unbounded_channel::<Option<graphics::Result>>();
+        let view_task = task::spawn(do_view_task(
+            cfg.clone(),
+            remote_in,
+            remote_out,
+            status_out,
+            PathBuf::from(VIEW_PROFILE_PATH),
+        ));
+
+        let tick_interval = cfg.cli_opt.tick_ms.map(Duration::from_millis);
+        let mut last_tick = Instant::now();
+        let wait = match tick_interval {
+            Some(interval) if interval < POLL_WAIT => interval,
+            _ => POLL_WAIT,
+        };
+
+        let mouse_motion_interval = Duration::from_millis(cfg.cli_opt.mouse_motion_ms);
+        let mut last_mouse_motion: Option<Instant> = None; // throttles mouse motion events.
+        let mut deferred_mouse_motion: Option<graphics::Pos> = None; // the latest throttled.
+
+        let mut actions: VecDeque<Action> = machine.start().into();
+        loop {
+            // Do the machine's actions, feeding back the ends of tasks.
+            while let Some(action) = actions.pop_front() {
+                match action {
+                    Action::View(dim, events) => {
+                        if view_out.send(Some((dim, events))).is_err() {
+                            actions.extend(machine.step(Input::TaskEnded(CallKind::View)));
+                        }
+                    }
+                    Action::Update(batch, events, req) => {
+                        outputs.events(&events);
+                        if update_out.send(Some((batch, events, req))).is_err() {
+                            actions.extend(machine.step(Input::TaskEnded(CallKind::Update)));
+                        }
+                    }
+                    Action::Retransmit(batch, events, req) => {
+                        if update_out.send(Some((batch, events, req))).is_err() {
+                            actions.extend(machine.step(Input::TaskEnded(CallKind::Update)));
+                        }
+                    }
+                    Action::Unconfirmed(batch, events) => outputs.unconfirmed(batch, &events),
+                    Action::Draw(rr) => outputs.redraw(machine.dim(), rr).await?,
+                    Action::Commands(cmds) => outputs.commands(&cmds),
+                    Action::Capture(graphics) => outputs.graphics(&graphics),
+                    Action::Finish => {}
+                }
+            }
+            if machine.phase() == Phase::Done {
+                break;
+            }
+
+            // Local interactions, via the input source (still polled while quitting, to
+            // quit again without waiting; ticks and mouse motion only while running).
+            let mut inputs = vec![];
+            let running = machine.phase() == Phase::Running;
+            let polled = input.is_some();
+            if polled {
+                if let Some(interval) = tick_interval.filter(|_| running) {
+                    let now = Instant::now();
+                    let elapsed = now.duration_since(last_tick);
+                    if elapsed >= interval {
+                        last_tick = now;
+                        let elapsed_ms = Nat::from(elapsed.as_millis() as u64);
+                        inputs.extend(event_input(&cfg, event::Event::Tick { elapsed_ms }));
+                    }
+                }
+                let motion_due = match last_mouse_motion {
+                    Some(last) => last.elapsed() >= mouse_motion_interval,
+                    None => true,
+                };
+                if running && motion_due {
+                    if let Some(pos) = deferred_mouse_motion.take() {
+                        last_mouse_motion = Some(Instant::now());
+                        trace!("MouseMove {:?} (deferred)", pos);
+                        inputs.extend(event_input(&cfg, event::Event::MouseMove(pos)));
+                    }
+                }
+                match poll_input(&mut input)? {
+                    None => {}
+                    Some(event::Event::Skip) | Some(event::Event::Tick { .. }) => {
+                        // ignore (ticks come from the local clock, above)
+                    }
+                    Some(event::Event::Quit) if running => {
+                        info!("Quit: waiting for the last updates (quit again to stop waiting)...");
+                        inputs.push(Input::Quit);
+                    }
+                    Some(event::Event::Quit) => {
+                        info!("Quit again: not waiting for the last update responses.");
+                        inputs.push(Input::Quit);
+                    }
+                    Some(event::Event::MouseMove(pos)) => {
+                        let now = Instant::now();
+                        let throttled = match last_mouse_motion {
+                            Some(last) => now.duration_since(last) < mouse_motion_interval,
+                            None => false,
+                        };
+                        if throttled {
+                            // send the latest position when the interval ends.
+                            deferred_mouse_motion = Some(pos);
+                        } else {
+                            last_mouse_motion = Some(now);
+                            deferred_mouse_motion = None;
+                            trace!("MouseMove {:?}", pos);
+                            inputs.extend(event_input(&cfg, event::Event::MouseMove(pos)));
+                        }
+                    }
+                    Some(event::Event::ClipBoard(ref text)) if outputs.is_clipboard_echo(text) => {
+                        debug!("ClipBoard: set by service; not echoing it back.");
+                    }
+                    Some(event::Event::WindowSize(new_dim)) => {
+                        info!("WindowSize {:?}", new_dim);
+                        outputs.resize(machine.dim(), &new_dim)?;
+                        inputs.push(Input::Resize(new_dim));
+                        inputs.extend(event_input(&cfg, event::Event::Skip));
+                    }
+                    Some(event::Event::FileRead { path, content }) => {
+                        info!("FileRead: {} ({} bytes)", path, content.len());
+                        inputs.extend(event_input(&cfg, event::Event::FileRead { path, content }));
+                    }
+                    Some(event) => {
+                        info!("{:?}", event);
+                        inputs.extend(event_input(&cfg, event));
+                    }
+                }
+            }
+
+            // Remote interactions, via the tasks' responses; without local input, wait for
+            // the first response (up to `wait`), rather than polling again at once.
+            while let Ok((kind, state)) = try_recv(&mut status_in) {
+                outputs.show_connection(kind, state);
+            }
+            let mut view_msg = try_recv(&mut view_in);
+            let mut update_msg = try_recv(&mut update_in);
+            let idle = matches!(
+                (&view_msg, &update_msg),
+                (Err(TryRecvError::Empty), Err(TryRecvError::Empty))
+            );
+            if inputs.is_empty() && idle {
+                let view_open = machine.view_state() != CallState::Ended;
+                tokio::select! {
+                    msg = view_in.recv(), if view_open => {
+                        view_msg = msg.ok_or(TryRecvError::Disconnected)
+                    }
+                    msg = update_in.recv() => update_msg = msg.ok_or(TryRecvError::Disconnected),
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+            match view_msg {
+                Ok(Some(rr)) => inputs.push(Input::ViewDone(rr)),
+                Ok(None) => inputs.push(Input::ViewFailed),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    if machine.view_state() != CallState::Ended {
+                        error!("View task ended.");
+                        inputs.push(Input::TaskEnded(CallKind::View));
+                    }
+                }
+            }
+            loop {
+                match update_msg {
+                    Ok((batch, Some(graphics))) => {
+                        debug!("batch {}: graphics.len() = {}", batch, graphics.len());
+                        inputs.push(Input::UpdateDone(batch, graphics));
+                    }
+                    Ok((batch, None)) => inputs.push(Input::UpdateFailed(batch)),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        error!("Update task ended.");
+                        inputs.push(Input::TaskEnded(CallKind::Update));
+                        break;
+                    }
+                }
+                update_msg = try_recv(&mut update_in);
+            }
+            for i in inputs {
+                actions.extend(machine.step(i));
+            }
+        }
+
+        info!("Session stats: {:?}", machine.stats());
+        outputs.finish(machine.dim())?;
+        let _ = update_out.send(None); // (the tasks may have ended already)
+        let _ = view_out.send(None);
+        let update_result = join_task(CallKind::Update, update_task).await;
+        let view_result = join_task(CallKind::View, view_task).await;
+        info!("All done.");
+        update_result.and(view_result)
+    }
+}
+
+/// Wait for a task to end, with its error, if any; a task that does not end soon
+/// (e.g., still retrying) is left to the runtime.
+async fn join_task(kind: CallKind, handle: task::JoinHandle<IcmtResult<()>>) -> IcmtResult<()> {
+    match tokio::time::timeout(TASK_END_TIMEOUT, handle).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(IcmtError::String(format!("{:?} task failed: {}", kind, e))),
+        Err(_) => {
+            warn!("{:?} task still busy; not waiting for it.", kind);
+            Ok(())
+        }
+    }
+}
+
+/// First nonce for a live session's events: the time, in microseconds since the epoch,
+/// so that nonces keep increasing from one session to the next.
+fn first_nonce() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// Poll the input source, if any.
+fn poll_input(input: &mut Option<Box<dyn InputSource>>) -> IcmtResult<Option<event::Event>> {
+    match input {
+        Some(input) => input.poll(),
+        None => Ok(None),
+    }
+}
+
+/// Why there was no message to receive (see `try_recv`).
+enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+/// Receive a message from a task, without waiting.
+fn try_recv<T>(rx: &mut UnboundedReceiver<T>) -> Result<T, TryRecvError> {
+    match rx.recv().now_or_never() {
+        Some(Some(msg)) => Ok(msg),
+        Some(None) => Err(TryRecvError::Disconnected),
+        None => Err(TryRecvError::Empty),
+    }
+}
+
+/// Input for a local event; `None` (ignoring it) unless the user is live, e.g., for
+/// the keys pressed in a replay's window.
+fn event_input(cfg: &ConnectCfg, event: event::Event) -> Option<Input> {
+    match local_event(cfg, event) {
+        Some(ev) => {
+            trace!("{:?}", ev.event);
+            Some(Input::Event(ev))
+        }
+        None => {
+            debug!("Not live; ignoring local event.");
+            None
+        }
+    }
+}
+
+/// A session's outputs: its render sinks and capture hooks, with what they show.
+struct Outputs {
+    sinks: Vec<Box<dyn RenderSink>>,
+    hooks: Vec<Box<dyn CaptureHook>>,
+    layers: Layers,
+    connection: Connection,
+    /// Clipboard text last set by the service (not to echo back to it).
+    clipboard_set: Option<String>,
+}
+
+impl Outputs {
+    fn new() -> Self {
+        Outputs {
+            sinks: vec![],
+            hooks: vec![],
+            layers: Layers::new(),
+            connection: Connection::new(),
+            clipboard_set: None,
+        }
+    }
+
+    async fn redraw(&mut self, dim: &graphics::Dim, data: graphics::Result) -> IcmtResult<()> {
+        self.layers.apply(data);
+        for sink in self.sinks.iter_mut() {
+            sink.render(dim, &self.layers).await?;
+        }
+        for hook in self.hooks.iter_mut() {
+            hook.frame(dim, &self.layers)
+                .await
+                .with_exit_code(exit_code::CAPTURE)?;
+        }
+        self.layers.clear_damage();
+        Ok(())
+    }
+
+    /// Do terminal commands from the service, with each sink.
+    fn commands(&mut self, cmds: &[graphics::Command]) {
+        for cmd in cmds.iter() {
+            info!("Command from service: {:?}", cmd);
+            let mut done = true;
+            for sink in self.sinks.iter_mut() {
+                if let Err(e) = sink.command(cmd) {
+                    error!("Cannot do command {:?}: {}", cmd, e);
+                    done = false;
+                }
+            }
+            if let (true, graphics::Command::SetClipBoard(text)) = (done, cmd) {
+                self.clipboard_set = Some(text.clone());
+            }
+        }
+    }
+
+    /// Is this clipboard update the echo of the service's own?
+    fn is_clipboard_echo(&mut self, text: &str) -> bool {
+        if self.clipboard_set.as_deref() == Some(text) {
+            self.clipboard_set = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn events(&mut self, events: &[event::EventInfo]) {
+        for hook in self.hooks.iter_mut() {
+            hook.events(events);
+        }
+    }
+
+    fn graphics(&mut self, graphics: &[graphics::Result]) {
+        for hook in self.hooks.iter_mut() {
+            hook.graphics(graphics);
+        }
+    }
+
+    fn resize(&mut self, old: &graphics::Dim, new: &graphics::Dim) -> IcmtResult<()> {
+        for hook in self.hooks.iter_mut() {
+            hook.resize(old, new).with_exit_code(exit_code::CAPTURE)?;
+        }
+        self.layers.invalidate();
+        Ok(())
+    }
+
+    fn finish(&mut self, dim: &graphics::Dim) -> IcmtResult<()> {
+        for hook in self.hooks.iter_mut() {
+            hook.finish(dim).with_exit_code(exit_code::CAPTURE)?;
+        }
+        Ok(())
+    }
+
+    /// Show the events of a failed update call that was not sent again: in the log, and
+    /// with each sink.
+    fn unconfirmed(&mut self, batch: BatchId, events: &[event::EventInfo]) {
+        error!(
+            "Update call of batch {} failed; its {} event(s) may or may not be done \
+             (they are sent again only with --canister-nonces).",
+            batch,
+            events.len()
+        );
+        self.connection.unconfirmed += events.len();
+        for sink in self.sinks.iter_mut() {
+            sink.connection(&self.connection);
+        }
+    }
+
+    /// Show a connection state change: in the log, and with each sink.
+    fn show_connection(&mut self, kind: CallKind, state: ConnectionState) {
+        match &state {
+            ConnectionState::Connected => info!("{:?} calls: {}", kind, state),
+            _ => warn!("{:?} calls: {}", kind, state),
+        }
+        if self.connection.set(kind, state) {
+            for sink in self.sinks.iter_mut() {
+                sink.connection(&self.connection);
+            }
+        }
+    }
+}
+
+type StatusSender = UnboundedSender<(CallKind, ConnectionState)>;
+
+/// Connect to the service: the configured backend, or else the canister, with a new agent
+/// (retrying transient errors, e.g., while the replica starts).
+async fn connect_service(
+    cfg: &ConnectCfg,
+    kind: CallKind,
+    status_out: &StatusSender,
+    data_path: &Path,
+) -> IcmtResult<Arc<dyn Service>> {
+    if let Some(service) = &cfg.service {
+        let _ = status_out.send((kind, ConnectionState::Connected));
+        return Ok(service.clone());
+    }
+    let mut backoff = Backoff::default();
+    loop {
+        match AgentService::connect(cfg, data_path.to_path_buf()).await {
+            Ok(service) => {
+                let _ = status_out.send((kind, ConnectionState::Connected));
+                return Ok(Arc::new(service));
+            }
+            Err(e) if e.is_transient() => {
+                match retry_delay(&mut backoff, kind, status_out, format!("{:?}", e)) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(e),
+                }
+            }
+            Err(e) => {
+                let _ = status_out.send((kind, ConnectionState::Failed(format!("{:?}", e))));
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Delay before retrying after a transient error, showing the retry; or else `None`,
+/// showing the failure, once the backoff gives up.
+fn retry_delay(
+    backoff: &mut Backoff,
+    kind: CallKind,
+    status_out: &StatusSender,
+    error: String,
+) -> Option<Duration> {
+    match backoff.next_delay() {
+        Some(delay) => {
+            let state = ConnectionState::Retrying {
+                attempt: backoff.attempt(),
+                error,
+            };
+            let _ = status_out.send((kind, state));
+            Some(delay)
+        }
+        None => {
+            let error = format!("{} (after {} retries)", error, backoff.attempt());
+            let _ = status_out.send((kind, ConnectionState::Failed(error)));
+            None
+        }
+    }
+}
+
+/// Answers each view request with its graphics, or with `None` after a transient
+/// failure (after a backoff delay), so that the last good frame stays on screen;
+/// gives up after too many transient failures in a row.
+async fn do_view_task(
+    cfg: ConnectCfg,
+    mut remote_in: UnboundedReceiver<Option<ViewCall>>,
+    remote_out: UnboundedSender<Option<graphics::Result>>,
+    status_out: StatusSender,
+    data_path: PathBuf,
+) -> IcmtResult<()> {
+    /* Create our own agent here since we cannot Send it here from the main thread. */
+    let service = connect_service(&cfg, CallKind::View, &status_out, &data_path).await?;
+    let mut backoff = Backoff::default();
+
+    loop {
+        match remote_in.recv().await.flatten() {
+            None => return Ok(()),
+            Some((window_dim, events)) => {
+                match service::call(service.as_ref(), ServiceCall::View(window_dim, events)).await {
+                    Ok(mut rr) => {
+                        if backoff.attempt() > 0 {
+                            let _ = status_out.send((CallKind::View, ConnectionState::Connected));
+                            backoff.reset();
+                        }
+                        assert_eq!(rr.len(), 1);
+                        remote_out.send(Some(rr.remove(0)))?;
+                    }
+                    Err(e) if e.is_transient() => {
+                        let error = format!("{:?}", e);
+                        match retry_delay(&mut backoff, CallKind::View, &status_out, error) {
+                            Some(delay) => tokio::time::sleep(delay).await,
+                            None => return Err(e),
+                        }
+                        remote_out.send(None)?;
+                    }
+                    Err(e) => {
+                        let state = ConnectionState::Failed(format!("{:?}", e));
+                        let _ = status_out.send((CallKind::View, state));
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A view call: the window size, and the events not yet done.
+type ViewCall = (graphics::Dim, Vec<event::EventInfo>);
+
+/// An update call: its batch of events, and the graphics to request.
+type UpdateCall = (BatchId, Vec<event::EventInfo>, graphics::Request);
+
+/// An update call's results, or `None` after it failed (transiently).
+type UpdateResponse = (BatchId, Option<Vec<graphics::Result>>);
+
+/// Makes update calls as they come, concurrently (the session limits how many are in
+/// flight). Answers each with its results, or with `None` after a transient failure
+/// (after a backoff delay), for the session to retransmit the batch; gives up after
+/// too many transient failures in a row.
+async fn do_update_task(
+    cfg: ConnectCfg,
+    mut remote_in: UnboundedReceiver<Option<UpdateCall>>,
+    remote_out: UnboundedSender<UpdateResponse>,
+    status_out: StatusSender,
+    data_path: PathBuf,
+) -> IcmtResult<()> {
+    /* Create our own agent here since we cannot Send it here from the main thread. */
+    let service = connect_service(&cfg, CallKind::Update, &status_out, &data_path).await?;
+    let mut calls = FuturesUnordered::new();
+    let mut retries = FuturesUnordered::new();
+    let mut backoff = Backoff::default();
+    loop {
+        tokio::select! {
+            call = remote_in.recv() => match call {
+                None | Some(None) => return Ok(()),
+                Some(Some((batch, events, req))) => {
+                    let service = service.clone();
+                    calls.push(async move {
+                        let call = ServiceCall::Update(events, req);
+                        (batch, service::call(service.as_ref(), call).await)
+                    });
+                }
+            },
+            Some((batch, result)) = calls.next() => match result {
+                Ok(r) => {
+                    if backoff.attempt() > 0 {
+                        let _ = status_out.send((CallKind::Update, ConnectionState::Connected));
+                        backoff.reset();
+                    }
+                    remote_out.send((batch, Some(r)))?;
+                }
+                Err(e) if e.is_transient() => {
+                    let error = format!("{:?}; batch {} unacknowledged", e, batch);
+                    let kind = CallKind::Update;
+                    let delay = match retry_delay(&mut backoff, kind, &status_out, error) {
+                        Some(delay) => delay,
+                        None => return Err(e),
+                    };
+                    retries.push(async move {
+                        tokio::time::sleep(delay).await;
+                        batch
+                    });
+                }
+                Err(e) => {
+                    let state = ConnectionState::Failed(format!("{:?}", e));
+                    let _ = status_out.send((CallKind::Update, state));
+                    return Err(e);
+                }
+            },
+            Some(batch) = retries.next() => remote_out.send((batch, None))?,
+        }
+    }
+}
@@ -1,68 +1,76 @@
 //! Types of data sent to and from the game service canister.
 
-use crate::cli::ConnectCtx;
+use crate::cli::ConnectCfg;
 use chrono::prelude::*;
-use num_traits::cast::ToPrimitive;
-pub type Nat = candid::Nat;
 
-/// temp hack: username and user-chosen color
-pub type UserInfoCli = (String, (Nat, Nat, Nat));
+pub use icmt_core::types::{
+    byte_ceil, event, graphics, lang, nat_ceil, nat_i32, nat_u32, nat_u8, Nat,
+};
+
+/// User name and user-chosen colors, for the local user's events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserInfoCli {
+    pub user_name: String,
+    pub fg_color: graphics::Color,
+    pub bg_color: graphics::Color,
+}
 
 /// User kind.
 #[derive(Debug, Clone)]
 pub enum UserKind {
     Local(UserInfoCli),
     Replay(Vec<event::EventInfo>),
-}
-
-pub fn nat_ceil(n: &Nat) -> u32 {
-    n.0.to_u32().unwrap()
-}
-
-pub fn byte_ceil(n: &Nat) -> u8 {
-    match n.0.to_u8() {
-        Some(byte) => byte,
-        None => 255,
-    }
+    /// Watches the view, without input.
+    Spectator,
 }
 
 /// user name.
-pub fn user_name(ctx: &ConnectCtx) -> Option<String> {
-    match &ctx.cfg.user_kind {
-        UserKind::Local(user_info) => Some(user_info.0.clone()),
-        UserKind::Replay(_) => None,
+pub fn user_name(cfg: &ConnectCfg) -> Option<String> {
+    match &cfg.user_kind {
+        UserKind::Local(user_info) => Some(user_info.user_name.clone()),
+        UserKind::Replay(_) | UserKind::Spectator => None,
     }
 }
 
-/// text color.
-pub fn text_color(ctx: &ConnectCtx) -> Option<(Nat, Nat, Nat)> {
-    match &ctx.cfg.user_kind {
-        UserKind::Local(user_info) => Some(user_info.1.clone()),
-        UserKind::Replay(_) => None,
+/// text color (foreground, background).
+pub fn text_color(cfg: &ConnectCfg) -> Option<(graphics::Color, graphics::Color)> {
+    match &cfg.user_kind {
+        UserKind::Local(user_info) => {
+            Some((user_info.fg_color.clone(), user_info.bg_color.clone()))
+        }
+        UserKind::Replay(_) | UserKind::Spectator => None,
     }
 }
 
-/// Form a skip event.
+/// Form a skip event (see `local_event`).
 ///
 /// Skip events do nothing but carry meta event info, needed for per-user views.
-pub fn skip_event(ctx: &ConnectCtx) -> event::EventInfo {
-    if let UserKind::Local(_) = ctx.cfg.user_kind {
-        event::EventInfo {
-            user_info: event::UserInfo {
-                user_name: user_name(ctx).unwrap(),
-                text_color: (
-                    text_color(ctx).unwrap(),
-                    (Nat::from(0), Nat::from(0), Nat::from(0)),
-                ),
-            },
-            nonce: None,
-            date_time_local: Local::now().to_rfc3339(),
-            date_time_utc: Utc::now().to_rfc3339(),
-            event: event::Event::Skip,
-        }
-    } else {
-        unimplemented!("skip events only come from live interaction, the Local user kind.")
-    }
+pub fn skip_event(cfg: &ConnectCfg) -> Option<event::EventInfo> {
+    local_event(cfg, event::Event::Skip)
+}
+
+/// Form a (full) event record for an event from the local user; `None` unless the
+/// interaction is live (the Local user kind), since only the local user has events.
+pub fn local_event(cfg: &ConnectCfg, event: event::Event) -> Option<event::EventInfo> {
+    Some(event::EventInfo {
+        user_info: event::UserInfo {
+            user_name: user_name(cfg)?,
+            text_color: text_color(cfg)?,
+        },
+        nonce: None,
+        date_time_local: Local::now().to_rfc3339(),
+        date_time_utc: Utc::now().to_rfc3339(),
+        event,
+    })
+}
+
+/// Form a file-read event, with the (UTF-8) content of the file at the given path.
+pub fn file_read_event(path: &str) -> std::io::Result<event::Event> {
+    let bytes = std::fs::read(path)?;
+    Ok(event::Event::FileRead {
+        path: path.to_string(),
+        content: String::from_utf8_lossy(&bytes).into_owned(),
+    })
 }
 
 /// Messages from terminal to service (IC canister).
@@ -75,188 +83,3 @@ pub enum ServiceCall {
     // To process user request to quit interaction
     FlushQuit,
 }
-
-/// Message language
-pub mod lang {
-    use super::Nat;
-    //use hashcons::merkle::Merkle;
-    use candid::{CandidType, Deserialize};
-
-    /// Directions in two dimensional space.
-    #[derive(Debug, Clone, CandidType, Deserialize, Eq, PartialEq, Hash)]
-    pub enum Dir2D {
-        #[serde(rename = "up")]
-        Up,
-        #[serde(rename = "down")]
-        Down,
-        #[serde(rename = "left")]
-        Left,
-        #[serde(rename = "right")]
-        Right,
-    }
-
-    /// Symbolic name (n-ary tree).
-    #[derive(Debug, Clone, CandidType, Deserialize, Eq, PartialEq, Hash)]
-    pub enum Name {
-        Void,
-        Atom(Atom),
-        TaggedTuple(Box<Name>, Vec<Name>),
-        //Merkle(Merkle<Name>),
-    }
-
-    /// Atomic name
-    #[derive(Debug, Clone, CandidType, Deserialize, Eq, PartialEq, Hash)]
-    pub enum Atom {
-        Bool(bool),
-        Nat(Nat),
-        String(String),
-    }
-}
-
-/// Terminal events, locally buffered as input to service.
-pub mod event {
-    use candid::{CandidType, Deserialize, Nat};
-
-    /// User information for identifying events' user origins.
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub struct UserInfo {
-        #[serde(rename = "userName")]
-        pub user_name: String,
-        #[serde(rename = "textColor")]
-        pub text_color: ((Nat, Nat, Nat), (Nat, Nat, Nat)),
-    }
-
-    /// Event information (full record).
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub struct EventInfo {
-        #[serde(rename = "userInfo")]
-        pub user_info: UserInfo,
-        pub nonce: Option<Nat>,
-        #[serde(rename = "dateTimeUtc")]
-        pub date_time_utc: String,
-        #[serde(rename = "dateTimeLocal")]
-        pub date_time_local: String,
-        pub event: Event,
-    }
-    /// Event(-specific information).
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub enum Event {
-        #[serde(rename = "skip")]
-        Skip,
-        #[serde(rename = "quit")]
-        Quit,
-        #[serde(rename = "keyDown")]
-        KeyDown(Vec<KeyEventInfo>),
-        #[serde(rename = "mouseDown")]
-        MouseDown(super::graphics::Pos),
-        #[serde(rename = "windowSize")]
-        WindowSize(super::graphics::Dim),
-        #[serde(rename = "clipBoard")]
-        ClipBoard(String),
-    }
-    /// Keyboard event information.
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub struct KeyEventInfo {
-        pub key: String,
-        pub alt: bool,
-        pub ctrl: bool,
-        pub meta: bool,
-        pub shift: bool,
-    }
-}
-
-/// Terminal gaphics, service output to terminal.
-pub mod graphics {
-    use super::Nat;
-    //use super::lang::Name;
-    use candid::{CandidType, Deserialize};
-
-    /// Color
-    pub type Color = (Nat, Nat, Nat);
-
-    /// (Update message's) request for graphics.
-    #[derive(Debug, Clone, CandidType, Deserialize, Eq, PartialEq, Hash)]
-    pub enum Request {
-        #[serde(rename = "none")]
-        None,
-        #[serde(rename = "all")]
-        All(Dim),
-        #[serde(rename = "last")]
-        Last(Dim),
-    }
-
-    /// Dimension
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub struct Dim {
-        pub width: Nat,
-        pub height: Nat,
-    }
-    /// Position
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub struct Pos {
-        pub x: Nat,
-        pub y: Nat,
-    }
-    /// Rectangle
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub struct Rect {
-        pub pos: Pos,
-        pub dim: Dim,
-    }
-    impl Rect {
-        pub fn new(x: Nat, y: Nat, w: Nat, h: Nat) -> Rect {
-            Rect {
-                pos: Pos { x, y },
-                dim: Dim {
-                    width: w,
-                    height: h,
-                },
-            }
-        }
-    }
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub struct Node {
-        pub rect: Rect,
-        pub fill: Fill,
-        pub elms: Elms,
-    }
-    /// Fill
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub enum Fill {
-        #[serde(rename = "open")]
-        Open(Color, Nat), // border width
-        #[serde(rename = "closed")]
-        Closed(Color),
-        #[serde(rename = "none")]
-        None,
-    }
-    /// Element
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub enum Elm {
-        #[serde(rename = "rect")]
-        Rect(Rect, Fill),
-        #[serde(rename = "node")]
-        Node(Box<Node>),
-    }
-    /// Elements
-    pub type Elms = Vec<Elm>;
-    /// Named elements
-    pub type NamedElms = Vec<(String, Elm)>;
-
-    /// Output
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub enum Out {
-        #[serde(rename = "draw")]
-        Draw(Elm),
-        #[serde(rename = "redraw")]
-        Redraw(NamedElms),
-    }
-    /// Result
-    #[derive(Clone, Debug, CandidType, Deserialize, Hash, PartialEq, Eq)]
-    pub enum Result {
-        #[serde(rename = "ok")]
-        Ok(Out),
-        #[serde(rename = "err")]
-        Err(Option<String>),
-    }
-}
@@ -0,0 +1,275 @@
+//! SDL window: its input (keyboard, mouse, clipboard and dropped files), and its output.
+
+use crate::cli::CliOpt;
+use crate::connection::Connection;
+use crate::draw::{create_surface_canvas, draw, Layers};
+use crate::error::{IcmtError, IcmtResult};
+use crate::keyboard::{Keyboard, Keymap};
+use crate::mouse;
+use crate::session::{InputSource, RenderSink};
+use crate::types::{event, file_read_event, graphics, nat_ceil};
+
+use async_trait::async_trait;
+use candid::{Int, Nat};
+use log::{error, trace};
+use sdl2::event::Event as SysEvent; // not to be confused with our own definition
+use sdl2::event::WindowEvent;
+use sdl2::render::Canvas;
+use sdl2::surface::Surface;
+use sdl2::video::Window;
+use std::io::{self, Write};
+
+pub fn create_window_canvas(
+    video_subsystem: &sdl2::VideoSubsystem,
+    window_dim: &graphics::Dim,
+) -> IcmtResult<Canvas<Window>> {
+    let window = video_subsystem
+        .window(
+            "IC Mini Terminal",
+            nat_ceil(&window_dim.width),
+            nat_ceil(&window_dim.height),
+        )
+        .position_centered()
+        .resizable()
+        /*.input_grabbed() // to do -- CI flag*/
+        .build()
+        .map_err(|e| e.to_string())?;
+    let canvas = window
+        .into_canvas()
+        .target_texture()
+        .present_vsync()
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok(canvas)
+}
+
+pub fn translate_system_event(
+    video_subsystem: &sdl2::VideoSubsystem,
+    keyboard: &mut Keyboard,
+    event: &SysEvent,
+) -> Option<event::Event> {
+    match event {
+        SysEvent::ClipboardUpdate { .. } => {
+            let text = match video_subsystem.clipboard().clipboard_text() {
+                Ok(text) => text,
+                Err(text) => format!("error: {}", text),
+            };
+            Some(event::Event::ClipBoard(text))
+        }
+        SysEvent::DropFile { filename, .. } => match file_read_event(filename) {
+            Ok(ev) => Some(ev),
+            Err(e) => {
+                error!("Cannot read dropped file {:?}: {}", filename, e);
+                None
+            }
+        },
+        SysEvent::Window {
+            win_event: WindowEvent::SizeChanged(w, h),
+            ..
+        } => {
+            let dim = graphics::Dim {
+                width: Nat::from(*w as u64),
+                height: Nat::from(*h as u64),
+            };
+            Some(event::Event::WindowSize(dim))
+        }
+        SysEvent::Quit { .. } => Some(event::Event::Quit),
+        SysEvent::KeyDown {
+            keycode: Some(ref kc),
+            keymod,
+            ..
+        } if keyboard.is_quit(kc, keymod) => Some(event::Event::Quit),
+        SysEvent::MouseButtonDown {
+            mouse_btn, x, y, ..
+        } => mouse::translate_button_event(mouse_btn, *x, *y).map(event::Event::MouseDown),
+        SysEvent::MouseButtonUp {
+            mouse_btn, x, y, ..
+        } => mouse::translate_button_event(mouse_btn, *x, *y).map(event::Event::MouseUp),
+        SysEvent::MouseMotion { x, y, .. } => {
+            Some(event::Event::MouseMove(mouse::translate_pos(*x, *y)))
+        }
+        SysEvent::MouseWheel {
+            x, y, direction, ..
+        } => {
+            let sign = match direction {
+                sdl2::mouse::MouseWheelDirection::Flipped => -1,
+                _ => 1,
+            };
+            Some(event::Event::MouseWheel {
+                dx: Int::from(sign * *x as i64),
+                dy: Int::from(sign * *y as i64),
+            })
+        }
+        SysEvent::TextInput { text, .. } if keyboard.text_input => {
+            let keymod = video_subsystem.sdl().keyboard().mod_state();
+            keyboard.text(text, &keymod)
+        }
+        SysEvent::TextEditing { text, .. } => {
+            // Composition in progress (e.g., a dead key, or an input method); the OS shows it.
+            // Its committed text arrives later, as TextInput.
+            trace!("TextEditing {:?}", text);
+            None
+        }
+        SysEvent::KeyDown {
+            keycode: Some(ref kc),
+            keymod,
+            repeat,
+            ..
+        } => keyboard.key_down(kc, keymod, *repeat),
+        SysEvent::KeyUp {
+            keycode: Some(ref kc),
+            keymod,
+            ..
+        } => keyboard.key_up(kc, keymod),
+        _ => None,
+    }
+}
+
+/// Input from the SDL window's events, with the configured keymap.
+pub struct SdlInput {
+    event_pump: sdl2::EventPump,
+    video_subsystem: sdl2::VideoSubsystem,
+    keyboard: Keyboard,
+}
+
+impl SdlInput {
+    pub fn new(
+        sdl: &sdl2::Sdl,
+        video_subsystem: &sdl2::VideoSubsystem,
+        cli_opt: &CliOpt,
+    ) -> IcmtResult<SdlInput> {
+        let keymap = match &cli_opt.keymap {
+            Some(path) => Keymap::from_file(path)?,
+            None => Keymap::default(),
+        };
+        let keyboard = Keyboard::new(keymap, !cli_opt.no_text_input, !cli_opt.no_repeat);
+        if keyboard.text_input {
+            video_subsystem.text_input().start();
+        } else {
+            video_subsystem.text_input().stop();
+        }
+        let event_pump = {
+            use sdl2::event::EventType;
+            let mut p = sdl.event_pump()?;
+            p.disable_event(EventType::FingerUp);
+            p.disable_event(EventType::FingerDown);
+            p.disable_event(EventType::FingerMotion);
+            p
+        };
+        Ok(SdlInput {
+            event_pump,
+            video_subsystem: video_subsystem.clone(),
+            keyboard,
+        })
+    }
+}
+
+impl InputSource for SdlInput {
+    fn poll(&mut self) -> IcmtResult<Option<event::Event>> {
+        // (skipping the system events that are not input, e.g., a window exposure.)
+        while let Some(system_event) = self.event_pump.poll_event() {
+            trace!("SDL event_pump.poll_event() => {:?}", &system_event);
+            let event =
+                translate_system_event(&self.video_subsystem, &mut self.keyboard, &system_event);
+            if event.is_some() {
+                return Ok(event);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Output to the SDL window: its canvas, title and clipboard (and the terminal's bell).
+///
+/// Graphics composite onto a surface, which keeps the last frame, so that only their
+/// damage is redrawn; each frame is then copied to the window as a texture.
+pub struct WindowSink {
+    canvas: Canvas<Window>,
+    frame: Option<Canvas<Surface<'static>>>,
+    video_subsystem: sdl2::VideoSubsystem,
+    title: String,
+    connection: Connection,
+    /// Draw graphics; otherwise, only do commands and show the connection state.
+    pub draw: bool,
+}
+
+impl WindowSink {
+    pub fn new(
+        video_subsystem: &sdl2::VideoSubsystem,
+        canvas: Canvas<Window>,
+        title: &str,
+    ) -> IcmtResult<WindowSink> {
+        let mut sink = WindowSink {
+            canvas,
+            frame: None,
+            video_subsystem: video_subsystem.clone(),
+            title: title.to_string(),
+            connection: Connection::new(),
+            draw: true,
+        };
+        sink.set_title()?;
+        Ok(sink)
+    }
+
+    fn set_title(&mut self) -> IcmtResult<()> {
+        let title = self.connection.title(&self.title);
+        self.canvas
+            .window_mut()
+            .set_title(&title)
+            .map_err(|e| IcmtError::String(format!("cannot set window title: {}", e)))
+    }
+}
+
+#[async_trait(?Send)]
+impl RenderSink for WindowSink {
+    async fn render(&mut self, dim: &graphics::Dim, layers: &Layers) -> IcmtResult<()> {
+        if !self.draw {
+            return Ok(());
+        }
+        let size = (nat_ceil(&dim.width), nat_ceil(&dim.height));
+        let fresh = match &self.frame {
+            Some(frame) => frame.surface().size() != size,
+            None => true,
+        };
+        if fresh {
+            self.frame = Some(create_surface_canvas(dim)?);
+        }
+        let frame = self.frame.as_mut().unwrap();
+        // (a fresh frame holds nothing yet, whatever the damage.)
+        draw(frame, dim, layers, !fresh).await?;
+        let texture_creator = self.canvas.texture_creator();
+        let texture = texture_creator
+            .create_texture_from_surface(frame.surface())
+            .map_err(|e| e.to_string())?;
+        self.canvas.copy(&texture, None, None)?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn command(&mut self, cmd: &graphics::Command) -> IcmtResult<()> {
+        match cmd {
+            graphics::Command::SetClipBoard(text) => self
+                .video_subsystem
+                .clipboard()
+                .set_clipboard_text(text)
+                .map_err(|e| IcmtError::String(format!("cannot set clipboard: {}", e))),
+            graphics::Command::SetTitle(title) => {
+                self.title = title.clone();
+                self.set_title()
+            }
+            graphics::Command::Bell => {
+                // SDL has no bell; ring the controlling terminal's.
+                print!("\x07");
+                let _ = io::stdout().flush();
+                Ok(())
+            }
+        }
+    }
+
+    fn connection(&mut self, connection: &Connection) {
+        self.connection = connection.clone();
+        if let Err(e) = self.set_title() {
+            error!("{}", e)
+        }
+    }
+}
@@ -1,8 +1,13 @@
+use async_trait::async_trait;
 use candid::Encode;
+use sdl2::render::Canvas;
+use sdl2::surface::Surface;
 use std::io::Write;
 
 use crate::cli::*;
-use crate::error::IcmtResult;
+use crate::draw::{create_surface_canvas, draw, Layers};
+use crate::error::{Context, IcmtResult};
+use crate::session::CaptureHook;
 use crate::types::{event, graphics};
 use chrono::prelude::*;
 
@@ -10,24 +15,25 @@ pub fn write_gifs(
     cli: &CliOpt,
     window_dim: &graphics::Dim,
     events: Vec<event::EventInfo>,
-    graphics: &Vec<graphics::Result>,
-    bmp_paths: &Vec<String>,
+    graphics: &[graphics::Result],
+    bmp_paths: &[String],
 ) -> IcmtResult<()> {
-    if bmp_paths.len() > 0 {
+    if !bmp_paths.is_empty() {
         use std::fs::File;
         let images = engiffen::load_images(bmp_paths);
         let gif = engiffen::engiffen(&images, cli.engiffen_frame_rate, engiffen::Quantizer::Naive)?;
         assert_eq!(gif.images.len(), bmp_paths.len());
         let local_time = Local::now().to_rfc3339();
-        if events.len() > 0 {
+        if !events.is_empty() {
             let events_path = format!(
                 "{}/icmt-{}-{}x{}-events.did",
                 cli.capture_output_path, local_time, window_dim.width, window_dim.height
             );
-            let mut output = File::create(&events_path)?;
+            let mut output = File::create(&events_path)
+                .context(|| format!("cannot create events capture {:?}", events_path))?;
             let events_bytes = Encode!(&events)?;
             let events_hex = hex::encode(&events_bytes);
-            output.write(&events_hex.as_bytes())?;
+            output.write_all(events_hex.as_bytes())?;
             println!(
                 "Wrote {} events as {} bytes to\n\t{}",
                 events.len(),
@@ -35,15 +41,16 @@ pub fn write_gifs(
                 events_path
             );
         }
-        if graphics.len() > 0 {
+        if !graphics.is_empty() {
             let graphics_path = format!(
                 "{}/icmt-{}-{}x{}-graphics.did",
                 cli.capture_output_path, local_time, window_dim.width, window_dim.height
             );
-            let mut output = File::create(&graphics_path)?;
+            let mut output = File::create(&graphics_path)
+                .context(|| format!("cannot create graphics capture {:?}", graphics_path))?;
             let graphics_bytes = Encode!(&graphics)?;
             let graphics_hex = hex::encode(&graphics_bytes);
-            output.write(&graphics_hex.as_bytes())?;
+            output.write_all(graphics_hex.as_bytes())?;
             println!(
                 "Wrote {} graphics frames as {} bytes to\n\t{}",
                 graphics.len(),
@@ -51,12 +58,13 @@ pub fn write_gifs(
                 graphics_path
             );
         }
-        if bmp_paths.len() > 0 {
+        if !bmp_paths.is_empty() {
             let video_path = format!(
                 "{}/icmt-{}-{}x{}-video.gif",
                 cli.capture_output_path, local_time, window_dim.width, window_dim.height
             );
-            let mut output = File::create(&video_path)?;
+            let mut output = File::create(&video_path)
+                .context(|| format!("cannot create video capture {:?}", video_path))?;
             gif.write(&mut output)?;
             println!(
                 "Wrote {} video frames to\n\t{}",
@@ -65,10 +73,82 @@ pub fn write_gifs(
             );
             println!("Removing {} .BMP files...", bmp_paths.len());
             for bmp_file in bmp_paths.iter() {
-                std::fs::remove_file(bmp_file)?;
+                std::fs::remove_file(bmp_file)
+                    .context(|| format!("cannot remove {:?}", bmp_file))?;
             }
             println!("Done: Removed {} .BMP files.", bmp_paths.len());
         }
     }
     Ok(())
 }
+
+/// Captures a session to files in the output path: its frames (as BMP files, then as
+/// a GIF for each window size), its events and its graphics.
+pub struct FileCapture {
+    cli: CliOpt,
+    canvas: Canvas<Surface<'static>>,
+    bmp_paths: Vec<String>,
+    events: Vec<event::EventInfo>,
+    graphics: Vec<graphics::Result>,
+}
+
+impl FileCapture {
+    pub fn new(cli: &CliOpt, window_dim: &graphics::Dim) -> IcmtResult<FileCapture> {
+        Ok(FileCapture {
+            cli: cli.clone(),
+            canvas: create_surface_canvas(window_dim)?,
+            bmp_paths: vec![],
+            events: vec![],
+            graphics: vec![],
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl CaptureHook for FileCapture {
+    fn events(&mut self, events: &[event::EventInfo]) {
+        self.events.extend_from_slice(events);
+    }
+
+    fn graphics(&mut self, graphics: &[graphics::Result]) {
+        self.graphics.extend_from_slice(graphics);
+    }
+
+    async fn frame(&mut self, window_dim: &graphics::Dim, layers: &Layers) -> IcmtResult<()> {
+        draw(&mut self.canvas, window_dim, layers, true).await?;
+        let path = format!(
+            "{}/screen-{}x{}-{}.bmp",
+            self.cli.capture_output_path,
+            window_dim.width,
+            window_dim.height,
+            Local::now().to_rfc3339()
+        );
+        self.canvas
+            .surface()
+            .save_bmp(&path)
+            .context(|| format!("cannot save screen capture {:?}", path))?;
+        self.bmp_paths.push(path);
+        Ok(())
+    }
+
+    fn resize(&mut self, old: &graphics::Dim, new: &graphics::Dim) -> IcmtResult<()> {
+        write_gifs(&self.cli, old, vec![], &[], &self.bmp_paths)?;
+        self.bmp_paths = vec![];
+        // Re-size canvas by re-creating it.
+        self.canvas = create_surface_canvas(new)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, window_dim: &graphics::Dim) -> IcmtResult<()> {
+        let events = std::mem::take(&mut self.events);
+        write_gifs(
+            &self.cli,
+            window_dim,
+            events,
+            &self.graphics,
+            &self.bmp_paths,
+        )?;
+        self.bmp_paths = vec![];
+        Ok(())
+    }
+}
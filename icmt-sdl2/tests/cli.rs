@@ -0,0 +1,121 @@
+//! Command line: parsing and validating options.
+
+use icmt_sdl2::cli::{parse_color, parse_tick_ms, CliCommand, CliOpt, MIN_TICK_MS};
+use icmt_sdl2::network::Network;
+use structopt::StructOpt;
+
+fn parse(args: &[&str]) -> Result<CliOpt, String> {
+    let args = ["ic-mt"].iter().chain(args.iter());
+    CliOpt::from_iter_safe(args).map_err(|e| e.message)
+}
+
+#[test]
+fn tick_intervals_below_the_minimum_are_rejected() {
+    assert_eq!(parse_tick_ms("10"), Ok(MIN_TICK_MS));
+    assert!(parse_tick_ms("9").unwrap_err().contains("too short"));
+    assert!(parse_tick_ms("0").is_err());
+    assert!(parse_tick_ms("ten").is_err());
+    assert!(parse(&["--tick-ms", "5", "connect", "counter"]).is_err());
+    let opt = parse(&["--tick-ms", "16", "connect", "counter"]).unwrap();
+    assert_eq!(opt.tick_ms, Some(16));
+}
+
+#[test]
+fn watch_intervals_below_the_minimum_are_rejected() {
+    assert!(parse(&["watch", "counter", "--interval-ms", "0"]).is_err());
+    assert!(parse(&["watch", "counter", "--interval-ms", "99"]).is_err());
+    let opt = parse(&["watch", "counter", "--interval-ms", "100"]).unwrap();
+    match opt.command {
+        CliCommand::Watch { interval_ms, .. } => assert_eq!(interval_ms, 100),
+        cmd => panic!("{:?}", cmd),
+    }
+}
+
+#[test]
+fn canister_nonces_are_opted_into() {
+    let opt = parse(&["--max-updates", "4", "connect", "counter"]).unwrap();
+    assert_eq!((opt.max_updates, opt.canister_nonces), (4, false));
+    let opt = parse(&["--canister-nonces", "--max-updates", "4", "connect", "counter"]).unwrap();
+    assert_eq!((opt.max_updates, opt.canister_nonces), (4, true));
+}
+
+#[test]
+fn colors_are_parsed_as_decimal_or_hex() {
+    assert_eq!(parse_color("255,128,0"), Ok((255, 128, 0)));
+    assert_eq!(parse_color(" 1, 2 ,3"), Ok((1, 2, 3)));
+    assert_eq!(parse_color("#ff8000"), Ok((255, 128, 0)));
+    for bad in &["256,0,0", "1,2", "1,2,3,4", "#ff80", "#gg0000", "red", ""] {
+        assert!(parse_color(bad).is_err(), "{:?}", bad);
+    }
+    let opt = parse(&["connect", "counter", "--fg-color", "#00ff00", "--bg-color", "0,0,255"]);
+    match opt.unwrap().command {
+        CliCommand::Connect {
+            fg_color, bg_color, ..
+        } => assert_eq!((fg_color, bg_color), (Some((0, 255, 0)), Some((0, 0, 255)))),
+        cmd => panic!("{:?}", cmd),
+    }
+    assert!(parse(&["connect", "counter", "--fg-color", "white"]).is_err());
+}
+
+#[test]
+fn networks_are_names_or_urls_with_a_scheme() {
+    assert_eq!("local".parse(), Ok(Network::Local));
+    assert_eq!("ic".parse(), Ok(Network::Ic));
+    assert_eq!(
+        "staging_2".parse(),
+        Ok(Network::Named("staging_2".to_string()))
+    );
+    assert_eq!(
+        "https://example.org:8443".parse(),
+        Ok(Network::Url("https://example.org:8443".to_string()))
+    );
+    for bad in &["127.0.0.1:8000", "localhost:8000", "ftp://example.org", ""] {
+        assert!(bad.parse::<Network>().is_err(), "{:?}", bad);
+    }
+    assert!(parse(&["connect", "counter", "--network", "127.0.0.1:8000"]).is_err());
+}
+
+#[test]
+fn a_legacy_replica_url_still_selects_the_network() {
+    let mut opt = parse(&[
+        "connect",
+        "http://127.0.0.1:8000",
+        "rwlgt-iiaaa-aaaaa-aaaaa-cai",
+    ])
+    .unwrap();
+    let url = opt.apply_legacy_replica_url().unwrap();
+    assert_eq!(url.as_deref(), Some("http://127.0.0.1:8000"));
+    match opt.command {
+        CliCommand::Connect {
+            canister_id,
+            network,
+            ..
+        } => {
+            assert_eq!(canister_id.as_deref(), Some("rwlgt-iiaaa-aaaaa-aaaaa-cai"));
+            assert_eq!(
+                network.network,
+                Some(Network::Url("http://127.0.0.1:8000".to_string()))
+            );
+        }
+        cmd => panic!("{:?}", cmd),
+    }
+
+    let mut opt = parse(&["connect", "counter"]).unwrap();
+    assert_eq!(opt.apply_legacy_replica_url(), Ok(None));
+
+    let mut opt = parse(&["connect", "127.0.0.1:8000", "counter"]).unwrap();
+    assert!(opt.apply_legacy_replica_url().is_err());
+
+    let args = [
+        "connect",
+        "--network",
+        "ic",
+        "http://127.0.0.1:8000",
+        "counter",
+    ];
+    let mut opt = parse(&args).unwrap();
+    assert!(opt
+        .apply_legacy_replica_url()
+        .unwrap_err()
+        .contains("--network"));
+}
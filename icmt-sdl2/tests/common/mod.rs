@@ -0,0 +1,74 @@
+//! Test support, shared by the tests.
+
+#![allow(dead_code)] // (each test uses only some of it)
+
+use icmt_sdl2::error::IcmtResult;
+use icmt_sdl2::session::InputSource;
+use icmt_sdl2::types::{event, graphics};
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// A key press, from the tester, without a nonce (as before it is sent).
+pub fn key_down(key: &str) -> event::EventInfo {
+    event::EventInfo {
+        user_info: event::UserInfo {
+            user_name: "tester".to_string(),
+            text_color: (graphics::color(255, 255, 255), graphics::color(0, 0, 0)),
+        },
+        nonce: None,
+        date_time_utc: String::new(),
+        date_time_local: String::new(),
+        event: event::Event::KeyDown(vec![event::KeyEventInfo {
+            key: key.to_string(),
+            alt: false,
+            ctrl: false,
+            meta: false,
+            shift: false,
+            repeat: Some(false),
+        }]),
+    }
+}
+
+/// Scripted input's pauses: as long as a session waits between polls, without input.
+pub const PAUSE: Duration = Duration::from_millis(13);
+
+/// Scripted input: its steps, one per poll, then a quit (and nothing after).
+pub struct Scripted {
+    steps: VecDeque<Option<event::Event>>,
+    /// End of the current pause, once it started.
+    paused_until: Option<Instant>,
+}
+
+impl Scripted {
+    pub fn new(events: Vec<event::Event>) -> Box<Scripted> {
+        Scripted::with_pauses(events.into_iter().map(Some).collect())
+    }
+
+    /// Input whose `None` steps are pauses (of `PAUSE` each), polled without input.
+    pub fn with_pauses(steps: Vec<Option<event::Event>>) -> Box<Scripted> {
+        let mut steps: VecDeque<_> = steps.into();
+        steps.push_back(Some(event::Event::Quit));
+        Box::new(Scripted {
+            steps,
+            paused_until: None,
+        })
+    }
+}
+
+impl InputSource for Scripted {
+    fn poll(&mut self) -> IcmtResult<Option<event::Event>> {
+        match self.steps.front() {
+            Some(Some(_)) => Ok(self.steps.pop_front().unwrap()),
+            Some(None) => {
+                let until = *self.paused_until.get_or_insert_with(|| Instant::now() + PAUSE);
+                if Instant::now() >= until {
+                    self.paused_until = None;
+                    self.steps.pop_front();
+                }
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+}
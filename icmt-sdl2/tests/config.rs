@@ -0,0 +1,37 @@
+//! Per-user configuration: settings remembered from one session to the next.
+
+use icmt_sdl2::config::UserConfig;
+use icmt_sdl2::types::graphics;
+
+#[test]
+fn user_settings_are_remembered() {
+    let dir = std::env::temp_dir().join(format!("icmt-config-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::env::set_var("ICMT_CONFIG_DIR", &dir);
+
+    // at first, a guest, with white text on black.
+    assert_eq!(UserConfig::load().unwrap(), None);
+    let guest = UserConfig::remember(None, None, None).unwrap();
+    assert!(guest.user_name.starts_with("Guest-"));
+    assert_eq!((guest.fg_color, guest.bg_color), ((255, 255, 255), (0, 0, 0)));
+
+    let alice = UserConfig::remember(Some("alice".to_string()), Some((255, 0, 0)), None).unwrap();
+    assert_eq!(UserConfig::load().unwrap(), Some(alice.clone()));
+
+    // later sessions keep the settings that they do not give.
+    let later = UserConfig::remember(None, None, Some((0, 0, 255))).unwrap();
+    assert_eq!(
+        later,
+        UserConfig {
+            user_name: "alice".to_string(),
+            fg_color: (255, 0, 0),
+            bg_color: (0, 0, 255),
+        }
+    );
+    assert_eq!(UserConfig::load().unwrap(), Some(later.clone()));
+    let user_info = later.user_info_cli();
+    assert_eq!(user_info.fg_color, graphics::color(255, 0, 0));
+    assert_eq!(user_info.bg_color, graphics::color(0, 0, 255));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,77 @@
+//! The in-process counter service, through the service protocol.
+
+mod common;
+
+use icmt_sdl2::counter::CounterService;
+use icmt_sdl2::service::{self, Service};
+use icmt_sdl2::types::{event, graphics, Nat, ServiceCall};
+
+use common::key_down;
+
+fn sent(key: &str, nonce: u64) -> event::EventInfo {
+    event::EventInfo {
+        nonce: Some(Nat::from(nonce)),
+        ..key_down(key)
+    }
+}
+
+fn drawn_text(result: &graphics::Result) -> String {
+    match result {
+        graphics::Result::Ok(graphics::Out::Draw(graphics::Elm::Text(t))) => t.text.clone(),
+        r => panic!("unexpected view result: {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn views_do_not_change_the_count() {
+    let counter = CounterService::new();
+    let dim = graphics::Dim::new(300, 100);
+    let view = counter
+        .view(dim.clone(), vec![key_down("+"), key_down("+")])
+        .await
+        .unwrap();
+    assert_eq!(drawn_text(&view), "count = 2");
+    assert_eq!(counter.count(), 0);
+}
+
+#[tokio::test]
+async fn updates_change_the_count() {
+    let counter = CounterService::new();
+    let dim = graphics::Dim::new(300, 100);
+    let events = vec![key_down("ArrowUp"), key_down("="), key_down("-"), key_down("+")];
+    let results = service::call(&counter, ServiceCall::Update(events, graphics::Request::None))
+        .await
+        .unwrap();
+    assert!(results.is_empty());
+    assert_eq!(counter.count(), 2);
+    let view = service::call(&counter, ServiceCall::View(dim, vec![key_down("ArrowLeft")]))
+        .await
+        .unwrap();
+    assert_eq!(drawn_text(&view[0]), "count = 1");
+}
+
+#[tokio::test]
+async fn events_are_done_once_by_nonce() {
+    let counter = CounterService::new();
+    let dim = graphics::Dim::new(300, 100);
+    let batch = vec![sent("+", 7), sent("+", 8)];
+    counter.update(batch.clone(), graphics::Request::None).await.unwrap();
+    // a batch sent again (e.g., after a timeout) is skipped.
+    counter.update(batch.clone(), graphics::Request::None).await.unwrap();
+    assert_eq!(counter.count(), 2);
+    // so are the events of a view that the counter did already.
+    let mut events = batch;
+    events.push(sent("+", 9));
+    let view = counter.view(dim, events).await.unwrap();
+    assert_eq!(drawn_text(&view), "count = 3");
+}
+
+#[tokio::test]
+async fn count_stops_at_zero() {
+    let counter = CounterService::new();
+    counter
+        .update(vec![key_down("-"), key_down("_")], graphics::Request::None)
+        .await
+        .unwrap();
+    assert_eq!(counter.count(), 0);
+}
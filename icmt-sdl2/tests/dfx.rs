@@ -0,0 +1,76 @@
+//! dfx projects: resolving networks and canister names to URLs and canister IDs.
+
+use icmt_sdl2::dfx::{resolve_in, DfxProject};
+use icmt_sdl2::network::{Network, IC_URL, LOCAL_URL};
+
+const LOCAL_ID: &str = "rrkah-fqaaa-aaaaa-aaaaq-cai";
+const IC_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+const STAGING_ID: &str = "rwlgt-iiaaa-aaaaa-aaaaa-cai";
+const STAGING_URL: &str = "https://staging.example.org";
+
+/// A project with a counter canister on the local network, the IC and a staging network.
+fn project() -> DfxProject {
+    let root = std::env::temp_dir().join(format!("icmt-dfx-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join(".dfx").join("local")).unwrap();
+    let dfx_json = format!(
+        r#"{{ "networks": {{ "staging": {{ "providers": ["{}/"], "type": "persistent" }} }} }}"#,
+        STAGING_URL
+    );
+    std::fs::write(root.join("dfx.json"), dfx_json).unwrap();
+    let ids = format!(
+        r#"{{ "counter": {{ "ic": "{}", "staging": "{}" }} }}"#,
+        IC_ID, STAGING_ID
+    );
+    std::fs::write(root.join("canister_ids.json"), ids).unwrap();
+    let local_ids = format!(r#"{{ "counter": {{ "local": "{}" }} }}"#, LOCAL_ID);
+    std::fs::write(root.join(".dfx/local/canister_ids.json"), local_ids).unwrap();
+    DfxProject::open(&root).unwrap()
+}
+
+#[test]
+fn canister_names_resolve_on_the_network_that_serves_them() {
+    let project = project();
+    let resolve = |network: Network| resolve_in(Some(&project), &network, "counter").unwrap();
+
+    assert_eq!(
+        resolve(Network::Local),
+        (LOCAL_URL.to_string(), Network::Local, LOCAL_ID.to_string())
+    );
+    assert_eq!(
+        resolve(Network::Ic),
+        (IC_URL.to_string(), Network::Ic, IC_ID.to_string())
+    );
+    let staging = Network::Url(format!("{}/", STAGING_URL));
+    assert_eq!(
+        resolve(Network::Named("staging".to_string())),
+        (format!("{}/", STAGING_URL), staging, STAGING_ID.to_string())
+    );
+    // a URL is the network that the project serves there.
+    let url = Network::Url(STAGING_URL.to_string());
+    assert_eq!(
+        resolve(url.clone()),
+        (STAGING_URL.to_string(), url, STAGING_ID.to_string())
+    );
+    let url = Network::Url(LOCAL_URL.to_string());
+    assert_eq!(
+        resolve(url.clone()),
+        (LOCAL_URL.to_string(), url, LOCAL_ID.to_string())
+    );
+}
+
+#[test]
+fn custom_urls_need_a_canister_id_or_a_matching_network() {
+    let project = project();
+    let other = Network::Url("https://other.example.org".to_string());
+    let err = resolve_in(Some(&project), &other, "counter").unwrap_err();
+    assert!(err.to_string().contains("give the canister ID"), "{}", err);
+    assert!(resolve_in(None, &other, "counter").is_err());
+    assert_eq!(
+        resolve_in(Some(&project), &other, IC_ID).unwrap(),
+        ("https://other.example.org".to_string(), other, IC_ID.to_string())
+    );
+
+    let err = resolve_in(Some(&project), &Network::Named("prod".to_string()), "counter");
+    assert!(err.unwrap_err().to_string().contains("unknown network"));
+}
@@ -0,0 +1,196 @@
+//! Golden-image tests for drawing.
+//!
+//! Each test draws into a small software surface and compares its pixels to a golden image,
+//! written as text: one character per pixel.
+
+use candid::Nat;
+use icmt_sdl2::draw::draw_elm;
+use icmt_sdl2::types::graphics::{self, Elm, Fill, Node, Pos, Rect, TextElm};
+use sdl2::pixels::PixelFormatEnum;
+
+fn rect(x: u32, y: u32, w: u32, h: u32) -> Rect {
+    Rect::new(Nat::from(x), Nat::from(y), Nat::from(w), Nat::from(h))
+}
+
+fn node(r: Rect, fill: Fill, elms: Vec<Elm>) -> Elm {
+    Elm::Node(Box::new(Node {
+        rect: r,
+        fill,
+        elms,
+    }))
+}
+
+fn text(x: u32, y: u32, text: &str, zoom: u32) -> Elm {
+    Elm::Text(TextElm {
+        pos: Pos::new(x, y),
+        text: text.to_string(),
+        color: graphics::color(255, 255, 255),
+        zoom: Nat::from(zoom),
+    })
+}
+
+const RED: (u8, u8, u8) = (255, 0, 0);
+const GREEN: (u8, u8, u8) = (0, 255, 0);
+const BLUE: (u8, u8, u8) = (0, 0, 255);
+const WHITE: (u8, u8, u8) = (255, 255, 255);
+
+fn closed((r, g, b): (u8, u8, u8)) -> Fill {
+    Fill::Closed(graphics::color(r, g, b))
+}
+
+fn open((r, g, b): (u8, u8, u8), width: u32) -> Fill {
+    Fill::Open(graphics::color(r, g, b), Nat::from(width))
+}
+
+fn pixel_char(rgb: &[u8]) -> char {
+    match (rgb[0], rgb[1], rgb[2]) {
+        (0, 0, 0) => '.',
+        (255, 255, 255) => '#',
+        (255, 0, 0) => 'r',
+        (0, 255, 0) => 'g',
+        (0, 0, 255) => 'b',
+        _ => '?',
+    }
+}
+
+/// Draw the elements on a black surface, and return its pixels as text.
+fn render(width: u32, height: u32, elms: Vec<Elm>) -> Vec<String> {
+    let surface = sdl2::surface::Surface::new(width, height, PixelFormatEnum::RGBA8888).unwrap();
+    let mut canvas = surface.into_canvas().unwrap();
+    canvas.set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
+    canvas.clear();
+    for elm in elms.iter() {
+        draw_elm(&mut canvas, &Pos::new(0, 0), elm).unwrap();
+    }
+    let pixels = canvas.read_pixels(None, PixelFormatEnum::RGB24).unwrap();
+    pixels
+        .chunks(width as usize * 3)
+        .map(|row| row.chunks(3).map(pixel_char).collect())
+        .collect()
+}
+
+fn assert_golden(actual: Vec<String>, golden: &[&str]) {
+    assert_eq!(
+        actual,
+        golden.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+        "\nactual:\n{}\n",
+        actual.join("\n")
+    );
+}
+
+#[test]
+fn open_fill_border_width() {
+    let elms = vec![Elm::Rect(rect(1, 1, 8, 6), open(WHITE, 2))];
+    assert_golden(
+        render(10, 8, elms),
+        &[
+            "..........",
+            ".########.",
+            ".########.",
+            ".##....##.",
+            ".##....##.",
+            ".########.",
+            ".########.",
+            "..........",
+        ],
+    );
+}
+
+#[test]
+fn open_fill_wide_border_covers_rect() {
+    let elms = vec![Elm::Rect(rect(1, 1, 4, 3), open(WHITE, 2))];
+    assert_golden(
+        render(6, 5, elms),
+        &["......", ".####.", ".####.", ".####.", "......"],
+    );
+}
+
+#[test]
+fn nested_nodes_clip_children() {
+    let inner = node(
+        rect(2, 2, 4, 3),
+        closed(RED),
+        vec![Elm::Rect(rect(1, 1, 10, 10), closed(GREEN))],
+    );
+    let outer = node(rect(1, 1, 8, 6), Fill::None, vec![inner]);
+    assert_golden(
+        render(10, 8, vec![outer]),
+        &[
+            "..........",
+            "..........",
+            "..........",
+            "...rrrr...",
+            "...rggg...",
+            "...rggg...",
+            "..........",
+            "..........",
+        ],
+    );
+}
+
+#[test]
+fn overlapping_nodes_paint_in_order() {
+    let a = node(rect(0, 0, 5, 4), closed(RED), vec![]);
+    let b = node(rect(3, 2, 5, 4), closed(BLUE), vec![]);
+    assert_golden(
+        render(8, 6, vec![a, b]),
+        &[
+            "rrrrr...",
+            "rrrrr...",
+            "rrrbbbbb",
+            "rrrbbbbb",
+            "...bbbbb",
+            "...bbbbb",
+        ],
+    );
+}
+
+#[test]
+fn edge_touching_nodes_do_not_bleed() {
+    let b = node(
+        rect(4, 0, 4, 4),
+        open(GREEN, 1),
+        vec![Elm::Rect(rect(1, 1, 10, 10), closed(BLUE))],
+    );
+    // drawn after b: unclipped, its child would paint over b.
+    let a = node(
+        rect(0, 0, 4, 4),
+        Fill::None,
+        vec![Elm::Rect(rect(2, 1, 10, 2), closed(RED))],
+    );
+    assert_golden(
+        render(8, 5, vec![b, a]),
+        &[
+            "....gggg",
+            "..rrgbbb",
+            "..rrgbbb",
+            "....gbbb",
+            "........",
+        ],
+    );
+}
+
+#[test]
+fn text_is_drawn_cell_by_cell() {
+    assert_golden(
+        render(7, 7, vec![text(1, 1, "ļ", 1)]),
+        &[
+            ".......",
+            ".#####.",
+            ".##.##.",
+            ".#.#.#.",
+            ".##.##.",
+            ".#####.",
+            ".......",
+        ],
+    );
+}
+
+#[test]
+fn text_with_overflowing_glyph_cells_is_skipped() {
+    // at this zoom, the first glyph's top-left cell covers the surface, and the third
+    // glyph's cells lie past i32::MAX.
+    let zoom = 1 << 28;
+    assert_golden(render(4, 2, vec![text(0, 0, "ļ", zoom)]), &["####", "####"]);
+    assert_golden(render(4, 2, vec![text(0, 0, "ļļļ", zoom)]), &["....", "...."]);
+}
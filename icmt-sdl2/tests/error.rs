@@ -0,0 +1,34 @@
+//! Errors: exit codes, through context and explicit codes.
+
+use icmt_sdl2::error::{exit_code, Context, ExitCode, IcmtError, IcmtResult};
+
+fn agent_error() -> IcmtResult<()> {
+    Err(ic_agent::AgentError::TimeoutWaitingForResponse().into())
+}
+
+fn io_error() -> IcmtResult<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::NotFound, "gone").into())
+}
+
+#[test]
+fn exit_codes_say_what_failed() {
+    assert_eq!(IcmtError::String("bad".into()).exit_code(), exit_code::OTHER);
+    assert_eq!(io_error().unwrap_err().exit_code(), exit_code::OTHER);
+    assert_eq!(agent_error().unwrap_err().exit_code(), exit_code::AGENT);
+
+    // context keeps the code of what failed.
+    let err = agent_error().context(|| "calling update").unwrap_err();
+    assert_eq!(err.to_string(), "calling update");
+    assert_eq!(err.exit_code(), exit_code::AGENT);
+
+    // an explicit code wins over the source's, and is kept under more context.
+    let err = io_error()
+        .context(|| "reading identity")
+        .with_exit_code(exit_code::IDENTITY)
+        .context(|| "connecting")
+        .unwrap_err();
+    assert_eq!(err.exit_code(), exit_code::IDENTITY);
+    let err = agent_error().with_exit_code(exit_code::CAPTURE).unwrap_err();
+    assert_eq!(err.exit_code(), exit_code::CAPTURE);
+    assert_eq!(err.to_string(), "service call failed");
+}
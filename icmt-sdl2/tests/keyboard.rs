@@ -0,0 +1,67 @@
+//! Keyboard: printable keys come from SDL text input, other keys from their keycodes.
+
+use icmt_sdl2::keyboard::{Keyboard, Keymap};
+use icmt_sdl2::types::event::{Event, KeyEventInfo};
+use sdl2::keyboard::{Keycode, Mod};
+
+fn key(key: &str, shift: bool, ctrl: bool) -> KeyEventInfo {
+    KeyEventInfo {
+        key: key.to_string(),
+        alt: false,
+        ctrl,
+        meta: false,
+        shift,
+        repeat: Some(false),
+    }
+}
+
+#[test]
+fn printable_keys_are_named_by_their_text_input() {
+    let mut keyboard = Keyboard::new(Keymap::default(), true, true);
+    // e.g., the US layout's Q key, on an AZERTY layout.
+    assert_eq!(keyboard.key_down(&Keycode::Q, &Mod::NOMOD, false), None);
+    assert_eq!(
+        keyboard.text("a", &Mod::NOMOD),
+        Some(Event::KeyDown(vec![key("a", false, false)]))
+    );
+    // the release is named after the press.
+    assert_eq!(
+        keyboard.key_up(&Keycode::Q, &Mod::NOMOD),
+        Some(Event::KeyUp(vec![key("a", false, false)]))
+    );
+    // composed text (e.g., from an input method) is a key press per character.
+    assert_eq!(
+        keyboard.text("é!", &Mod::LSHIFTMOD),
+        Some(Event::KeyDown(vec![key("é", true, false), key("!", true, false)]))
+    );
+}
+
+#[test]
+fn other_keys_are_named_by_their_keycodes() {
+    let mut keyboard = Keyboard::new(Keymap::default(), true, true);
+    assert_eq!(
+        keyboard.key_down(&Keycode::Left, &Mod::NOMOD, false),
+        Some(Event::KeyDown(vec![key("ArrowLeft", false, false)]))
+    );
+    // chords with Ctrl have no text input.
+    assert_eq!(
+        keyboard.key_down(&Keycode::C, &Mod::LCTRLMOD, false),
+        Some(Event::KeyDown(vec![key("c", false, true)]))
+    );
+}
+
+#[test]
+fn without_text_input_printable_keys_are_named_by_their_keycodes() {
+    let mut keyboard = Keyboard::new(Keymap::default(), false, true);
+    assert_eq!(
+        keyboard.key_down(&Keycode::Num2, &Mod::LSHIFTMOD, false),
+        Some(Event::KeyDown(vec![key("@", true, false)]))
+    );
+}
+
+#[test]
+fn auto_repeated_text_input_is_suppressed_with_its_key() {
+    let mut keyboard = Keyboard::new(Keymap::default(), true, false);
+    assert_eq!(keyboard.key_down(&Keycode::A, &Mod::NOMOD, true), None);
+    assert_eq!(keyboard.text("a", &Mod::NOMOD), None);
+}
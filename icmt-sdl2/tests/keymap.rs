@@ -0,0 +1,103 @@
+//! Keymaps: parsing their chords, and their bindings' precedence over the built-in key names.
+
+use icmt_sdl2::keyboard::{KeyChord, Keymap};
+use sdl2::keyboard::{Keycode, Mod};
+
+fn translated(keymap: &Keymap, keycode: Keycode, keymod: Mod) -> Option<String> {
+    keymap
+        .translate_event(&keycode, &keymod)
+        .map(|info| info.key)
+}
+
+#[test]
+fn chords_are_parsed_with_their_modifiers() {
+    let keymap: Keymap = r#"(
+        quit: [(keycode: "F10")],
+        keys: [(chord: (keycode: "Q", ctrl: Some(true), shift: Some(false)), key: "q")],
+    )"#
+    .parse()
+    .unwrap();
+    assert_eq!(
+        keymap.quit,
+        vec![KeyChord {
+            keycode: Keycode::F10,
+            shift: None,
+            ctrl: None,
+            alt: None,
+            meta: None,
+        }]
+    );
+    assert_eq!(
+        keymap.keys[0].chord,
+        KeyChord {
+            keycode: Keycode::Q,
+            shift: Some(false),
+            ctrl: Some(true),
+            alt: None,
+            meta: None,
+        }
+    );
+}
+
+#[test]
+fn unknown_key_names_are_rejected() {
+    let err = "(keys: [(chord: (keycode: \"NoSuchKey\"), key: \"x\")])"
+        .parse::<Keymap>()
+        .unwrap_err();
+    assert!(err.contains("NoSuchKey"), "{}", err);
+}
+
+#[test]
+fn quit_defaults_to_escape() {
+    let keymap: Keymap = "(keys: [])".parse().unwrap();
+    assert_eq!(keymap, Keymap::default());
+    assert!(keymap.is_quit(&Keycode::Escape, &Mod::NOMOD));
+    assert!(!keymap.is_quit(&Keycode::F10, &Mod::NOMOD));
+}
+
+#[test]
+fn modifiers_must_match_when_given() {
+    let keymap: Keymap = "(keys: [(chord: (keycode: \"Q\", ctrl: Some(true)), key: \"quit\")])"
+        .parse()
+        .unwrap();
+    assert!(keymap.keys[0].chord.matches(&Keycode::Q, &Mod::LCTRLMOD));
+    assert!(keymap.keys[0].chord.matches(&Keycode::Q, &Mod::RCTRLMOD));
+    assert!(!keymap.keys[0].chord.matches(&Keycode::Q, &Mod::NOMOD));
+    // modifiers that are not given match either way.
+    assert!(keymap.keys[0]
+        .chord
+        .matches(&Keycode::Q, &(Mod::LCTRLMOD | Mod::LSHIFTMOD)));
+}
+
+#[test]
+fn user_bindings_take_precedence_over_built_in_names() {
+    let keymap: Keymap = r#"(keys: [
+        (chord: (keycode: "Escape"), key: "Esc"),
+        (chord: (keycode: "F5", shift: Some(true)), key: "Reload"),
+        (chord: (keycode: "F5"), key: "Refresh"),
+    ])"#
+    .parse()
+    .unwrap();
+    assert_eq!(
+        translated(&keymap, Keycode::Escape, Mod::NOMOD),
+        Some("Esc".to_string())
+    );
+    // the first binding that matches wins.
+    assert_eq!(
+        translated(&keymap, Keycode::F5, Mod::LSHIFTMOD),
+        Some("Reload".to_string())
+    );
+    assert_eq!(
+        translated(&keymap, Keycode::F5, Mod::NOMOD),
+        Some("Refresh".to_string())
+    );
+    // unbound keys keep their built-in names.
+    assert_eq!(
+        translated(&keymap, Keycode::F6, Mod::NOMOD),
+        Some("F6".to_string())
+    );
+    assert_eq!(
+        translated(&Keymap::default(), Keycode::Escape, Mod::NOMOD),
+        Some("Escape".to_string())
+    );
+}
@@ -0,0 +1,76 @@
+//! Layers: which region each change of the service's graphics damages.
+
+use candid::Nat;
+use icmt_sdl2::draw::{Damage, Layers};
+use icmt_sdl2::types::graphics::{self, Elm, Fill, Out, Rect};
+
+fn rect_elm(x: u32, y: u32, w: u32, h: u32, (r, g, b): (u8, u8, u8)) -> Elm {
+    Elm::Rect(
+        Rect::new(Nat::from(x), Nat::from(y), Nat::from(w), Nat::from(h)),
+        Fill::Closed(graphics::color(r, g, b)),
+    )
+}
+
+fn redraw(layers: Vec<(&str, Elm)>) -> graphics::Result {
+    let layers = layers.into_iter().map(|(n, e)| (n.to_string(), e));
+    graphics::Result::Ok(Out::Redraw(layers.collect()))
+}
+
+fn region(x: i32, y: i32, w: u32, h: u32) -> Damage {
+    Damage::Region(sdl2::rect::Rect::new(x, y, w, h))
+}
+
+const RED: (u8, u8, u8) = (255, 0, 0);
+const BLUE: (u8, u8, u8) = (0, 0, 255);
+
+/// Layers with a background and a cursor, as drawn (without damage).
+fn drawn() -> Layers {
+    let mut layers = Layers::new();
+    layers.apply(redraw(vec![
+        ("background", rect_elm(0, 0, 100, 80, BLUE)),
+        ("cursor", rect_elm(10, 20, 5, 5, RED)),
+    ]));
+    layers.clear_damage();
+    layers
+}
+
+#[test]
+fn new_layers_are_fully_damaged() {
+    assert_eq!(Layers::new().damage(), Damage::Full);
+}
+
+#[test]
+fn unchanged_layers_give_no_damage() {
+    let mut layers = drawn();
+    layers.apply(redraw(vec![("cursor", rect_elm(10, 20, 5, 5, RED))]));
+    layers.set("background".to_string(), rect_elm(0, 0, 100, 80, BLUE));
+    assert_eq!(layers.damage(), Damage::Clean);
+}
+
+#[test]
+fn changed_layers_damage_only_their_rects() {
+    let mut layers = drawn();
+    layers.apply(redraw(vec![("cursor", rect_elm(10, 20, 5, 5, BLUE))]));
+    assert_eq!(layers.damage(), region(10, 20, 5, 5));
+
+    // a move damages where the layer was, and where it is.
+    let mut layers = drawn();
+    layers.set("cursor".to_string(), rect_elm(30, 20, 5, 5, RED));
+    assert_eq!(layers.damage(), region(10, 20, 25, 5));
+
+    // so does a new layer, where it is.
+    let mut layers = drawn();
+    layers.set("status".to_string(), rect_elm(0, 70, 100, 10, RED));
+    assert_eq!(layers.damage(), region(0, 70, 100, 10));
+}
+
+#[test]
+fn invalidated_and_replaced_layers_are_fully_damaged() {
+    let mut layers = drawn();
+    layers.invalidate();
+    assert_eq!(layers.damage(), Damage::Full);
+
+    let mut layers = drawn();
+    layers.apply(graphics::Result::Ok(Out::Draw(rect_elm(10, 20, 5, 5, RED))));
+    assert_eq!(layers.damage(), Damage::Full);
+}
@@ -0,0 +1,350 @@
+//! The session state machine, driven by scripted inputs and responses.
+
+mod common;
+
+use icmt_sdl2::connection::CallKind;
+use icmt_sdl2::machine::{Action, CallState, Input, Machine, Phase};
+use icmt_sdl2::types::{event, graphics, Nat};
+
+use common::key_down;
+
+/// The event, as sent in an update call, with its nonce.
+fn sent(key: &str, nonce: u64) -> event::EventInfo {
+    event::EventInfo {
+        nonce: Some(Nat::from(nonce)),
+        ..key_down(key)
+    }
+}
+
+fn dim() -> graphics::Dim {
+    graphics::Dim::new(300, 100)
+}
+
+fn frame() -> graphics::Result {
+    graphics::Result::Ok(graphics::Out::Draw(graphics::Elm::Text(
+        graphics::TextElm {
+            pos: graphics::Pos::new(0, 0),
+            text: "frame".to_string(),
+            color: graphics::color(255, 255, 255),
+            zoom: Nat::from(1u32),
+        },
+    )))
+}
+
+/// A live machine, started: its first update (batch 1) and view calls are in flight.
+fn started(all_graphics: bool, max_updates: usize) -> Machine {
+    let mut m = Machine::live(vec![key_down("a")], dim(), all_graphics).max_updates(max_updates);
+    let req = if all_graphics {
+        graphics::Request::All(dim())
+    } else {
+        graphics::Request::None
+    };
+    assert_eq!(
+        m.start(),
+        vec![
+            Action::Update(1, vec![sent("a", 0)], req),
+            Action::View(dim(), vec![sent("a", 0)]),
+        ]
+    );
+    m
+}
+
+#[test]
+fn events_wait_for_the_update_in_flight() {
+    let mut m = started(false, 1);
+    assert_eq!(m.step(Input::Event(key_down("b"))), vec![]);
+    assert_eq!(m.step(Input::Event(key_down("c"))), vec![]);
+    // the view call has the in-flight update's events, then the pending ones (numbered).
+    assert_eq!(
+        m.step(Input::ViewDone(frame())),
+        vec![
+            Action::Draw(frame()),
+            Action::View(dim(), vec![sent("a", 0), sent("b", 1), sent("c", 2)]),
+        ]
+    );
+    assert_eq!(
+        m.step(Input::UpdateDone(1, vec![])),
+        vec![
+            Action::Capture(vec![]),
+            Action::Update(2, vec![sent("b", 1), sent("c", 2)], graphics::Request::None),
+        ]
+    );
+    // (the view is in flight, and stale)
+    assert_eq!(
+        m.step(Input::ViewDone(frame())),
+        vec![
+            Action::Draw(frame()),
+            Action::View(dim(), vec![sent("b", 1), sent("c", 2)]),
+        ]
+    );
+    assert_eq!(m.stats().update_requests, 2);
+    assert_eq!(m.stats().view_requests, 3);
+}
+
+#[test]
+fn updates_keep_polling_for_commands() {
+    let mut m = started(true, 1);
+    let cmds = vec![graphics::Command::Bell];
+    let results = vec![graphics::Result::Ok(graphics::Out::Commands(cmds.clone()))];
+    assert_eq!(
+        m.step(Input::UpdateDone(1, results.clone())),
+        vec![
+            Action::Commands(cmds),
+            Action::Capture(results),
+            Action::Update(2, vec![], graphics::Request::All(dim())),
+        ]
+    );
+}
+
+#[test]
+fn updates_are_pipelined() {
+    let mut m = started(false, 3);
+    let none = graphics::Request::None;
+    // each event goes out at once, while there are free slots.
+    assert_eq!(
+        m.step(Input::Event(key_down("b"))),
+        vec![Action::Update(2, vec![sent("b", 1)], none.clone())]
+    );
+    assert_eq!(
+        m.step(Input::Event(key_down("c"))),
+        vec![Action::Update(3, vec![sent("c", 2)], none.clone())]
+    );
+    assert_eq!(m.updates_in_flight(), 3);
+    assert_eq!(m.step(Input::Event(key_down("d"))), vec![]);
+    assert_eq!(m.step(Input::Event(key_down("e"))), vec![]);
+    // acknowledgements may come out of order.
+    assert_eq!(
+        m.step(Input::UpdateDone(2, vec![])),
+        vec![
+            Action::Capture(vec![]),
+            Action::Update(4, vec![sent("d", 3), sent("e", 4)], none),
+        ]
+    );
+    assert_eq!(
+        m.step(Input::ViewDone(frame())),
+        vec![
+            Action::Draw(frame()),
+            Action::View(
+                dim(),
+                vec![sent("a", 0), sent("c", 2), sent("d", 3), sent("e", 4)]
+            ),
+        ]
+    );
+    // without calls in flight, one (empty) call polls for commands.
+    m.step(Input::UpdateDone(1, vec![]));
+    m.step(Input::UpdateDone(3, vec![]));
+    assert_eq!(
+        m.step(Input::UpdateDone(4, vec![])),
+        vec![
+            Action::Capture(vec![]),
+            Action::Update(5, vec![], graphics::Request::None),
+        ]
+    );
+    assert_eq!(m.updates_in_flight(), 1);
+}
+
+#[test]
+fn failed_updates_are_retransmitted() {
+    let mut m = started(false, 2);
+    m.step(Input::Event(key_down("b")));
+    assert_eq!(
+        m.step(Input::UpdateFailed(1)),
+        vec![Action::Retransmit(
+            1,
+            vec![sent("a", 0)],
+            graphics::Request::None
+        )]
+    );
+    assert_eq!(m.stats().update_retransmits, 1);
+    assert_eq!(m.updates_in_flight(), 2);
+    m.step(Input::UpdateDone(1, vec![]));
+    // a late failure, or a second response, of an acknowledged batch does nothing.
+    assert_eq!(m.step(Input::UpdateFailed(1)), vec![]);
+    assert_eq!(m.step(Input::UpdateDone(1, vec![])), vec![]);
+    assert_eq!(m.stats().update_responses, 1);
+}
+
+#[test]
+fn failed_updates_are_kept_unconfirmed_without_retransmits() {
+    let mut m = Machine::live(vec![key_down("a")], dim(), false).retransmit(false);
+    m.start();
+    m.step(Input::Event(key_down("b")));
+    // (the service may have done the batch, so it is reported, but not sent again.)
+    assert_eq!(
+        m.step(Input::UpdateFailed(1)),
+        vec![
+            Action::Unconfirmed(1, vec![sent("a", 0)]),
+            Action::Update(2, vec![sent("b", 1)], graphics::Request::None)
+        ]
+    );
+    assert_eq!(m.stats().update_unconfirmed, 1);
+    assert_eq!(m.stats().update_retransmits, 0);
+    assert_eq!(m.updates_in_flight(), 1);
+    assert_eq!(m.unconfirmed().get(&1), Some(&vec![sent("a", 0)]));
+}
+
+#[test]
+fn nonces_start_from_the_first_nonce() {
+    let mut m = Machine::live(vec![key_down("a"), key_down("b")], dim(), false).first_nonce(1000);
+    assert_eq!(
+        m.start()[0],
+        Action::Update(
+            1,
+            vec![sent("a", 1000), sent("b", 1001)],
+            graphics::Request::None
+        )
+    );
+}
+
+#[test]
+fn failed_views_are_asked_again() {
+    let mut m = started(false, 1);
+    assert_eq!(m.view_state(), CallState::InFlight);
+    assert_eq!(
+        m.step(Input::ViewFailed),
+        vec![Action::View(dim(), vec![sent("a", 0)])]
+    );
+    assert_eq!(m.stats().view_failures, 1);
+}
+
+#[test]
+fn resizes_ask_for_a_view_of_the_new_size() {
+    let mut m = started(false, 1);
+    let new_dim = graphics::Dim::new(600, 200);
+    assert_eq!(m.step(Input::Resize(new_dim.clone())), vec![]);
+    assert_eq!(
+        m.step(Input::ViewDone(frame())),
+        vec![
+            Action::Draw(frame()),
+            Action::View(new_dim.clone(), vec![sent("a", 0)]),
+        ]
+    );
+    assert_eq!(m.dim(), &new_dim);
+}
+
+#[test]
+fn quitting_sends_pending_events_then_finishes() {
+    let mut m = started(false, 1);
+    m.step(Input::Event(key_down("b")));
+    assert_eq!(m.step(Input::Quit), vec![]);
+    assert_eq!(m.phase(), Phase::Quitting);
+    // events after quitting are not sent.
+    m.step(Input::Event(key_down("c")));
+    assert_eq!(
+        m.step(Input::UpdateDone(1, vec![])),
+        vec![
+            Action::Capture(vec![]),
+            Action::Update(2, vec![sent("b", 1)], graphics::Request::None),
+        ]
+    );
+    assert_eq!(m.phase(), Phase::Quitting);
+    assert_eq!(
+        m.step(Input::UpdateDone(2, vec![])),
+        vec![Action::Capture(vec![]), Action::Finish]
+    );
+    assert_eq!(m.phase(), Phase::Done);
+    assert_eq!(m.step(Input::ViewDone(frame())), vec![]);
+}
+
+#[test]
+fn quitting_waits_for_all_acknowledgements() {
+    let mut m = started(false, 2);
+    m.step(Input::Event(key_down("b")));
+    m.step(Input::Quit);
+    assert_eq!(
+        m.step(Input::UpdateDone(2, vec![])),
+        vec![Action::Capture(vec![])]
+    );
+    assert_eq!(m.phase(), Phase::Quitting);
+    assert_eq!(
+        m.step(Input::UpdateFailed(1)),
+        vec![Action::Retransmit(
+            1,
+            vec![sent("a", 0)],
+            graphics::Request::None
+        )]
+    );
+    assert_eq!(
+        m.step(Input::UpdateDone(1, vec![])),
+        vec![Action::Capture(vec![]), Action::Finish]
+    );
+}
+
+#[test]
+fn quitting_without_pending_events_finishes_after_the_update() {
+    let mut m = started(false, 1);
+    m.step(Input::Quit);
+    assert_eq!(
+        m.step(Input::UpdateDone(1, vec![])),
+        vec![Action::Capture(vec![]), Action::Finish]
+    );
+}
+
+#[test]
+fn quitting_again_finishes_without_waiting() {
+    let mut m = started(false, 1);
+    m.step(Input::Event(key_down("b")));
+    m.step(Input::Quit);
+    assert_eq!(m.step(Input::Quit), vec![Action::Finish]);
+    assert_eq!(m.phase(), Phase::Done);
+    assert_eq!(m.updates_in_flight(), 0);
+    // the dropped batch's response comes too late.
+    assert_eq!(m.step(Input::UpdateDone(1, vec![])), vec![]);
+}
+
+#[test]
+fn the_end_of_the_view_task_quits() {
+    let mut m = started(false, 1);
+    m.step(Input::Event(key_down("b")));
+    assert_eq!(m.step(Input::TaskEnded(CallKind::View)), vec![]);
+    assert_eq!(m.phase(), Phase::Quitting);
+    assert_eq!(
+        m.step(Input::UpdateDone(1, vec![])),
+        vec![
+            Action::Capture(vec![]),
+            Action::Update(2, vec![sent("b", 1)], graphics::Request::None),
+        ]
+    );
+}
+
+#[test]
+fn the_end_of_the_update_task_finishes() {
+    let mut m = started(false, 1);
+    m.step(Input::Event(key_down("b")));
+    assert_eq!(
+        m.step(Input::TaskEnded(CallKind::Update)),
+        vec![Action::Finish]
+    );
+    assert_eq!(m.phase(), Phase::Done);
+    assert_eq!(m.update_state(), CallState::Ended);
+}
+
+#[test]
+fn replays_send_frames_then_finish() {
+    let events = vec![key_down("a"), key_down("b"), key_down("c")];
+    // (replays are not pipelined, and keep their events' nonces)
+    let mut m = Machine::replay(events, 2, dim()).max_updates(4);
+    let all = graphics::Request::All(dim());
+    assert_eq!(
+        m.start(),
+        vec![
+            Action::Update(1, vec![key_down("a"), key_down("b")], all.clone()),
+            Action::View(dim(), vec![]),
+        ]
+    );
+    // replays do not do the service's commands.
+    let results = vec![graphics::Result::Ok(graphics::Out::Commands(vec![
+        graphics::Command::Bell,
+    ]))];
+    assert_eq!(
+        m.step(Input::UpdateDone(1, results.clone())),
+        vec![
+            Action::Capture(results),
+            Action::Update(2, vec![key_down("c")], all),
+        ]
+    );
+    assert_eq!(
+        m.step(Input::UpdateDone(2, vec![])),
+        vec![Action::Capture(vec![]), Action::Finish]
+    );
+}
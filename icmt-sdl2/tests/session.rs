@@ -0,0 +1,509 @@
+//! Sessions, embedded with an in-process service and a capture hook (without SDL).
+
+mod common;
+
+use icmt_sdl2::cli::{CliOpt, ConnectCfg};
+use icmt_sdl2::connection::{Connection, ConnectionState};
+use icmt_sdl2::counter::CounterService;
+use icmt_sdl2::error::{IcmtError, IcmtResult};
+use icmt_sdl2::network::RootKey;
+use icmt_sdl2::service::Service;
+use icmt_sdl2::draw::Layers;
+use icmt_sdl2::session::{CaptureHook, RenderSink, Session};
+use icmt_sdl2::types::{event, file_read_event, graphics, nat_u32, UserInfoCli, UserKind};
+
+use async_trait::async_trait;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use structopt::StructOpt;
+
+use common::{key_down, Scripted};
+
+#[derive(Default)]
+struct Captured {
+    events: Vec<event::EventInfo>,
+    finished: bool,
+}
+
+struct Recorder(Rc<RefCell<Captured>>);
+
+impl CaptureHook for Recorder {
+    fn events(&mut self, events: &[event::EventInfo]) {
+        self.0.borrow_mut().events.extend_from_slice(events);
+    }
+
+    fn finish(&mut self, _dim: &graphics::Dim) -> IcmtResult<()> {
+        self.0.borrow_mut().finished = true;
+        Ok(())
+    }
+}
+
+/// Service that rejects every call.
+#[derive(Debug)]
+struct Rejecting;
+
+#[async_trait]
+impl Service for Rejecting {
+    async fn view(
+        &self,
+        _dim: graphics::Dim,
+        _events: Vec<event::EventInfo>,
+    ) -> IcmtResult<graphics::Result> {
+        Err(IcmtError::String("view rejected".to_string()))
+    }
+
+    async fn update(
+        &self,
+        _events: Vec<event::EventInfo>,
+        _req: graphics::Request,
+    ) -> IcmtResult<Vec<graphics::Result>> {
+        Err(IcmtError::String("update rejected".to_string()))
+    }
+}
+
+/// Service that answers each update with terminal commands.
+#[derive(Debug)]
+struct Commanding(Vec<graphics::Command>);
+
+#[async_trait]
+impl Service for Commanding {
+    async fn view(
+        &self,
+        _dim: graphics::Dim,
+        _events: Vec<event::EventInfo>,
+    ) -> IcmtResult<graphics::Result> {
+        Ok(graphics::Result::Ok(graphics::Out::Redraw(vec![])))
+    }
+
+    async fn update(
+        &self,
+        _events: Vec<event::EventInfo>,
+        _req: graphics::Request,
+    ) -> IcmtResult<Vec<graphics::Result>> {
+        let cmds = graphics::Out::Commands(self.0.clone());
+        Ok(vec![graphics::Result::Ok(cmds)])
+    }
+}
+
+/// Sink that records the commands that it does.
+struct CommandSink(Rc<RefCell<Vec<graphics::Command>>>);
+
+#[async_trait(?Send)]
+impl RenderSink for CommandSink {
+    async fn render(&mut self, _dim: &graphics::Dim, _layers: &Layers) -> IcmtResult<()> {
+        Ok(())
+    }
+
+    fn command(&mut self, cmd: &graphics::Command) -> IcmtResult<()> {
+        self.0.borrow_mut().push(cmd.clone());
+        Ok(())
+    }
+}
+
+/// Service that counts its calls, and draws nothing.
+#[derive(Debug, Default)]
+struct Tally {
+    views: AtomicUsize,
+    updates: AtomicUsize,
+}
+
+#[async_trait]
+impl Service for Tally {
+    async fn view(
+        &self,
+        _dim: graphics::Dim,
+        _events: Vec<event::EventInfo>,
+    ) -> IcmtResult<graphics::Result> {
+        self.views.fetch_add(1, Ordering::SeqCst);
+        Ok(graphics::Result::Ok(graphics::Out::Redraw(vec![])))
+    }
+
+    async fn update(
+        &self,
+        _events: Vec<event::EventInfo>,
+        _req: graphics::Request,
+    ) -> IcmtResult<Vec<graphics::Result>> {
+        self.updates.fetch_add(1, Ordering::SeqCst);
+        Ok(vec![])
+    }
+}
+
+/// Slow service, that counts the update calls that it has at once, and the events that
+/// it does; its first `failures` updates fail (transiently).
+#[derive(Debug, Default)]
+struct Slow {
+    orders: bool,
+    failures: AtomicUsize,
+    in_flight: AtomicUsize,
+    most_in_flight: AtomicUsize,
+    events: AtomicUsize,
+}
+
+#[async_trait]
+impl Service for Slow {
+    async fn view(
+        &self,
+        _dim: graphics::Dim,
+        _events: Vec<event::EventInfo>,
+    ) -> IcmtResult<graphics::Result> {
+        Ok(graphics::Result::Ok(graphics::Out::Redraw(vec![])))
+    }
+
+    async fn update(
+        &self,
+        events: Vec<event::EventInfo>,
+        _req: graphics::Request,
+    ) -> IcmtResult<Vec<graphics::Result>> {
+        let fail = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if fail.is_ok() {
+            return Err(ic_agent::AgentError::TimeoutWaitingForResponse().into());
+        }
+        let n = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.most_in_flight.fetch_max(n, Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(40)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.events.fetch_add(events.len(), Ordering::SeqCst);
+        Ok(vec![])
+    }
+
+    fn dedupes_nonces(&self) -> bool {
+        self.orders
+    }
+
+    fn orders_nonces(&self) -> bool {
+        self.orders
+    }
+}
+
+/// Sink that keeps the last connection state that it showed.
+struct ConnectionSink(Rc<RefCell<Connection>>);
+
+#[async_trait(?Send)]
+impl RenderSink for ConnectionSink {
+    async fn render(&mut self, _dim: &graphics::Dim, _layers: &Layers) -> IcmtResult<()> {
+        Ok(())
+    }
+
+    fn connection(&mut self, connection: &Connection) {
+        *self.0.borrow_mut() = connection.clone();
+    }
+}
+
+fn replay_cfg(events: Vec<event::EventInfo>) -> ConnectCfg {
+    let cli_opt = CliOpt::from_iter(&[
+        "ic-mt",
+        "--no-capture",
+        "replay",
+        "counter",
+        "events.did",
+        "--frame-size",
+        "3",
+    ]);
+    ConnectCfg {
+        cli_opt,
+        canister_id: "counter".to_string(),
+        replica_url: "in-process:counter".to_string(),
+        root_key: RootKey::Builtin,
+        user_kind: UserKind::Replay(events),
+        pem_file: None,
+        send_files: vec![],
+        service: None,
+    }
+}
+
+/// Configuration for a live session, with these options (before the command).
+fn live_cfg(opts: &[&str]) -> ConnectCfg {
+    let args = ["ic-mt", "--no-capture"].iter().chain(opts.iter());
+    let cli_opt = CliOpt::from_iter(args.chain(["connect", "counter"].iter()));
+    ConnectCfg {
+        cli_opt,
+        canister_id: "counter".to_string(),
+        replica_url: "in-process:counter".to_string(),
+        root_key: RootKey::Builtin,
+        user_kind: UserKind::Local(UserInfoCli {
+            user_name: "tester".to_string(),
+            fg_color: graphics::color(255, 255, 255),
+            bg_color: graphics::color(0, 0, 0),
+        }),
+        pem_file: None,
+        send_files: vec![],
+        service: None,
+    }
+}
+
+/// Run a live session with the counter and scripted input; the events that it sent.
+async fn run_live(cfg: ConnectCfg, input: Box<Scripted>) -> Vec<event::EventInfo> {
+    let captured = Rc::new(RefCell::new(Captured::default()));
+    Session::new(cfg)
+        .service(Arc::new(CounterService::new()))
+        .input(input)
+        .capture(Box::new(Recorder(captured.clone())))
+        .run()
+        .await
+        .unwrap();
+    let captured = captured.borrow();
+    assert!(captured.finished);
+    captured.events.clone()
+}
+
+/// A file in a fresh temporary directory, with the content.
+fn temp_file(name: &str, content: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("icmt-session-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, content).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn sent_and_dropped_files_are_file_read_events() {
+    let sent = temp_file("sent.txt", "sent on connect");
+    let dropped = temp_file("dropped.txt", "dropped on the window");
+    let drop_event = file_read_event(&dropped).unwrap();
+    let cfg = ConnectCfg {
+        send_files: vec![sent.clone()],
+        ..live_cfg(&[])
+    };
+    let events = run_live(cfg, Scripted::new(vec![drop_event])).await;
+    let files: Vec<(String, String)> = events
+        .into_iter()
+        .filter_map(|ev| match ev.event {
+            event::Event::FileRead { path, content } => Some((path, content)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            (sent, "sent on connect".to_string()),
+            (dropped, "dropped on the window".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn throttled_mouse_motion_sends_the_latest_position() {
+    let mut steps = vec![
+        Some(event::Event::MouseMove(graphics::Pos::new(1, 1))),
+        Some(event::Event::MouseMove(graphics::Pos::new(2, 2))),
+        Some(event::Event::MouseMove(graphics::Pos::new(3, 3))),
+    ];
+    // (these pauses outlast the interval.)
+    steps.extend(vec![None; 20]);
+    let cfg = live_cfg(&["--mouse-motion-ms", "200"]);
+    let events = run_live(cfg, Scripted::with_pauses(steps)).await;
+    let moves: Vec<graphics::Pos> = events
+        .into_iter()
+        .filter_map(|ev| match ev.event {
+            event::Event::MouseMove(pos) => Some(pos),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(moves, vec![graphics::Pos::new(1, 1), graphics::Pos::new(3, 3)]);
+}
+
+#[tokio::test]
+async fn service_commands_go_to_the_sinks_without_echoing_the_clipboard() {
+    use graphics::Command::{Bell, SetClipBoard, SetTitle};
+    let cmds = vec![
+        SetClipBoard("copied".to_string()),
+        SetTitle("Board".to_string()),
+        Bell,
+    ];
+    let done = Rc::new(RefCell::new(vec![]));
+    let captured = Rc::new(RefCell::new(Captured::default()));
+    // the window reports the service's clipboard text back, then the user's.
+    let mut steps = vec![None; 3];
+    steps.push(Some(event::Event::ClipBoard("copied".to_string())));
+    steps.push(Some(event::Event::ClipBoard("mine".to_string())));
+    Session::new(live_cfg(&[]))
+        .service(Arc::new(Commanding(cmds.clone())))
+        .input(Scripted::with_pauses(steps))
+        .sink(Box::new(CommandSink(done.clone())))
+        .capture(Box::new(Recorder(captured.clone())))
+        .run()
+        .await
+        .unwrap();
+    // (each update's response has the commands again.)
+    assert_eq!(done.borrow()[..3], cmds[..]);
+    let clipboard: Vec<String> = captured
+        .borrow()
+        .events
+        .iter()
+        .filter_map(|ev| match &ev.event {
+            event::Event::ClipBoard(text) => Some(text.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(clipboard, vec!["mine".to_string()]);
+}
+
+#[tokio::test]
+async fn ticks_are_sent_at_the_interval() {
+    let cfg = live_cfg(&["--tick-ms", "30"]);
+    let started = std::time::Instant::now();
+    let events = run_live(cfg, Scripted::with_pauses(vec![None; 12])).await;
+    let elapsed = started.elapsed().as_millis() as u64;
+    let ticks: Vec<u64> = events
+        .into_iter()
+        .filter_map(|ev| match ev.event {
+            event::Event::Tick { elapsed_ms } => Some(nat_u32(&elapsed_ms).unwrap() as u64),
+            _ => None,
+        })
+        .collect();
+    assert!(!ticks.is_empty());
+    assert!(ticks.len() as u64 <= elapsed / 30, "{} ticks in {} ms", ticks.len(), elapsed);
+    for tick in ticks {
+        assert!(tick >= 30, "tick after {} ms", tick);
+    }
+}
+
+#[tokio::test]
+async fn watching_makes_only_view_calls() {
+    let cli_opt = CliOpt::from_iter(&[
+        "ic-mt",
+        "--no-capture",
+        "watch",
+        "counter",
+        "--interval-ms",
+        "100",
+    ]);
+    let cfg = ConnectCfg {
+        cli_opt,
+        user_kind: UserKind::Spectator,
+        ..live_cfg(&[])
+    };
+    let tally = Arc::new(Tally::default());
+    // (the pauses outlast a few intervals; input such as keys is ignored.)
+    let mut steps = vec![Some(event::Event::ClipBoard("ignored".to_string()))];
+    steps.extend(vec![None; 30]);
+    Session::new(cfg)
+        .service(tally.clone())
+        .input(Scripted::with_pauses(steps))
+        .run()
+        .await
+        .unwrap();
+    assert!(tally.views.load(Ordering::SeqCst) >= 2);
+    assert_eq!(tally.updates.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn several_updates_are_in_flight_with_services_that_order_nonces() {
+    for &orders in &[true, false] {
+        let slow = Arc::new(Slow {
+            orders,
+            ..Slow::default()
+        });
+        // (keys come faster than the updates are done.)
+        let mut steps = vec![];
+        for _ in 0..8 {
+            steps.push(Some(key_down("+").event));
+            steps.push(None);
+        }
+        Session::new(live_cfg(&["--max-updates", "3"]))
+            .service(slow.clone())
+            .input(Scripted::with_pauses(steps))
+            .run()
+            .await
+            .unwrap();
+        let most = slow.most_in_flight.load(Ordering::SeqCst);
+        if orders {
+            assert!((2..=3).contains(&most), "{} updates in flight", most);
+        } else {
+            assert_eq!(most, 1);
+        }
+        // (the skip event, then the keys.)
+        assert_eq!(slow.events.load(Ordering::SeqCst), 9);
+    }
+}
+
+#[tokio::test]
+async fn failed_updates_are_reported_and_not_sent_again_without_nonce_dedupes() {
+    let slow = Arc::new(Slow {
+        failures: AtomicUsize::new(1),
+        ..Slow::default()
+    });
+    let connection = Rc::new(RefCell::new(Connection::new()));
+    // (the first update, of the skip event, fails, and is reported before the key.)
+    let mut steps = vec![None; 20];
+    steps.push(Some(key_down("+").event));
+    Session::new(live_cfg(&[]))
+        .service(slow.clone())
+        .input(Scripted::with_pauses(steps))
+        .sink(Box::new(ConnectionSink(connection.clone())))
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(connection.borrow().unconfirmed, 1);
+    assert_eq!(connection.borrow().update, ConnectionState::Connected);
+    assert_eq!(slow.events.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn replay_updates_the_service() {
+    let events = vec![key_down("+"), key_down("+"), key_down("-"), key_down("+")];
+    let cfg = replay_cfg(events.clone());
+    let counter = Arc::new(CounterService::new());
+    let captured = Rc::new(RefCell::new(Captured::default()));
+    Session::new(cfg)
+        .service(counter.clone())
+        .capture(Box::new(Recorder(captured.clone())))
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(counter.count(), 2);
+    let captured = captured.borrow();
+    assert_eq!(captured.events, events);
+    assert!(captured.finished);
+}
+
+#[tokio::test]
+async fn replays_ignore_window_input() {
+    let events = vec![key_down("+"), key_down("+")];
+    let counter = Arc::new(CounterService::new());
+    let captured = Rc::new(RefCell::new(Captured::default()));
+    let key = key_down("-").event;
+    let mut steps = vec![
+        Some(key),
+        Some(event::Event::MouseMove(graphics::Pos::new(1, 1))),
+        Some(event::Event::WindowSize(graphics::Dim::new(100, 100))),
+    ];
+    // (the replay ends during these pauses, before the quit.)
+    steps.extend(vec![None; 10]);
+    let cfg = ConnectCfg {
+        cli_opt: CliOpt::from_iter(&[
+            "ic-mt",
+            "--no-capture",
+            "--tick-ms",
+            "10",
+            "replay",
+            "counter",
+            "events.did",
+        ]),
+        ..replay_cfg(events.clone())
+    };
+    Session::new(cfg)
+        .service(counter.clone())
+        .input(Scripted::with_pauses(steps))
+        .capture(Box::new(Recorder(captured.clone())))
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(counter.count(), 2);
+    assert_eq!(captured.borrow().events, events);
+}
+
+#[tokio::test]
+async fn failed_calls_fail_the_session() {
+    let err = Session::new(replay_cfg(vec![key_down("+")]))
+        .service(Arc::new(Rejecting))
+        .run()
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "update rejected");
+}
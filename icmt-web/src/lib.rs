@@ -1,22 +1,13 @@
 use wasm_bindgen::prelude::*;
 
-use std::f64;
-
 use wasm_bindgen::JsCast;
 use web_sys::{self, console};
 
-use std::cell::Cell;
-use std::rc::Rc;
-
 extern crate icmt_core;
-use icmt_core::{
-    types::{
-        event::{self, Event, KeyEventInfo}, graphics, nat_ceil
-    },
-};
+use icmt_core::types::event::{Event, KeyEventInfo};
 
 fn console_log(m: String) {
-    let message: JsValue = m.as_str().clone().into();
+    let message: JsValue = m.as_str().into();
     console::log_1(&message);
 }
 
@@ -26,7 +17,7 @@ pub async fn main() -> Result<(), JsValue> {
 
     let closure = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
 
-        match format!("{}", event.key()).as_str() {
+        match event.key().as_str() {
             "Tab" | "Escape" | "ArrowUp" | "ArrowDown" | "ArrowLeft" | "ArrowRight" | " "
                 | "Backspace" => {
                     let ev =
@@ -36,6 +27,7 @@ pub async fn main() -> Result<(), JsValue> {
                             ctrl: event.ctrl_key(),
                             shift: event.shift_key(),
                             meta: event.meta_key(),
+                            repeat: Some(event.repeat()),
                         }]);
                     drop(ev)
                 }